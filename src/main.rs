@@ -1,27 +1,192 @@
 // lisp inspired language
 // very good very nice
 
-use std::{env, fs, path::Path, process::exit, collections::HashMap, io::Write};
+use std::{env, fs, process::{Command, Stdio}, path::{Component, Path, PathBuf}, process::exit, collections::HashMap, io::{BufRead, BufReader, BufWriter, Read, Write}, rc::Rc, cell::{Cell, RefCell}, cmp::Ordering, sync::mpsc::{channel, Receiver, Sender}};
 
 #[derive(Debug, Clone)]
 #[derive(PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 enum TokenKind {
     FUNCTION,
     STRING,
     INT,
+    FLOAT,
     VOID,
     BOOL,
     WORD,
     ARRAY,
+    LAMBDA,
+    RANGE,
+    GENERATOR,
+    MAP,
+    CODE,
 }
 
 #[derive(Debug, Clone)]
 struct Token {
     kind: TokenKind,
     context: String,
-    body: Vec<Token>
+    // shared between copies of the value, changing it clones the items only if they are shared
+    body: Rc<Vec<Token>>,
+    // position in source, 0 for values created while interpreting
+    line: usize,
+    pos: usize,
+    // running body of generator, shared between copies so they continue where any of them stopped
+    coroutine: Option<Rc<Coroutine>>,
+}
+
+impl Token {
+    fn new(kind: TokenKind, context: String, body: Vec<Token>) -> Token {
+        Token::shared(kind, context, Rc::new(body))
+    }
+
+    fn shared(kind: TokenKind, context: String, body: Rc<Vec<Token>>) -> Token {
+        Token { kind, context, body, line: 0, pos: 0, coroutine: None }
+    }
+}
+
+// frames, calls, module, file and stack limit of code which runs on one thread
+#[derive(Default)]
+struct Context {
+    frames: Vec<HashMap<String, Token>>,
+    call_stack: Vec<String>,
+    module: String,
+    file: PathBuf,
+    stack_end: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CoroutineState {
+    Created,
+    Running,
+    Suspended,
+    Finished,
+}
+
+// body of generator runs on its own thread, so it can stop at yield and continue when next value is needed
+// threads take turns, the one which hands over waits until it gets the interpreter back
+struct Coroutine {
+    // context of generator while it waits, context of its consumer while it runs
+    context: RefCell<Context>,
+    // value of last yield, or result of the body when it ended
+    step: RefCell<Option<Result<Option<Token>, Signal>>>,
+    state: Cell<CoroutineState>,
+    // wakes the generator thread, false tells it to stop
+    resume: RefCell<Option<Sender<bool>>>,
+    // generator thread yielded or ended
+    steps: RefCell<Option<Receiver<()>>>,
+    // suspended generator which is dropped is stopped later, when the interpreter is in consistent state
+    abandoned: Option<Rc<RefCell<Vec<Rc<Coroutine>>>>>,
+}
+
+impl Drop for Coroutine {
+    fn drop(&mut self) {
+        if self.state.get() != CoroutineState::Suspended {
+            return;
+        }
+        let Some(abandoned) = self.abandoned.take() else { return };
+        abandoned.borrow_mut().push(Rc::new(Coroutine {
+            context: RefCell::new(self.context.take()),
+            step: RefCell::new(None),
+            state: Cell::new(CoroutineState::Suspended),
+            resume: RefCell::new(self.resume.take()),
+            steps: RefCell::new(self.steps.take()),
+            abandoned: None,
+        }));
+    }
+}
+
+impl std::fmt::Debug for Coroutine {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(formatter, "Coroutine({:?})", self.state.get())
+    }
+}
+
+// moves values which are not Send to generator thread, threads never use the interpreter at the same time
+struct Handover<T>(T);
+
+unsafe impl<T> Send for Handover<T> {}
+
+impl<T> Handover<T> {
+    fn take(self) -> T {
+        self.0
+    }
+}
+
+thread_local! {
+    // generator thread's side of channels to its consumer
+    static HANDOVER: RefCell<Option<(Sender<()>, Receiver<bool>)>> = const { RefCell::new(None) };
+}
+
+// control flow which unwinds through parse_token until something handles it
+#[derive(Debug, Clone)]
+enum Signal {
+    Break,
+    Continue,
+    Return(Token),
+    TailCall(Token, Vec<Token>),
+    Error(String, usize, usize),
+    // ends the program with exit code after output is flushed
+    Exit(i32),
+    // stops generator which is no longer used, travels back through its body
+    Stop,
+}
+
+// operand of arithmetic, integers stay integers unless mixed with floats
+#[derive(Debug, Clone, Copy)]
+enum Number {
+    Int(isize),
+    Float(f64),
+}
+
+impl Number {
+    fn float(self) -> f64 {
+        match self {
+            Number::Int(value) => value as f64,
+            Number::Float(value) => value,
+        }
+    }
+}
+
+impl PartialEq for Number {
+    fn eq(&self, other: &Number) -> bool {
+        self.partial_cmp(other) == Some(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for Number {
+    fn partial_cmp(&self, other: &Number) -> Option<Ordering> {
+        match (self, other) {
+            (Number::Int(left), Number::Int(right)) => Some(left.cmp(right)),
+            _ => self.float().partial_cmp(&other.float()),
+        }
+    }
 }
 
+// receives items of iterable one by one, boxed so it can outlive the call which started iteration
+type Consumer = Box<dyn FnMut(&mut Interpreter, Token) -> Result<(), Signal>>;
+
+// standard library is part of the binary, so it always matches version of the interpreter
+const STD: [(&str, &str); 3] = [
+    ("std/math", include_str!("../std/math.wal")),
+    ("std/strings", include_str!("../std/strings.wal")),
+    ("std/arrays", include_str!("../std/arrays.wal")),
+];
+
+// tests of standard library, run with --test std
+const STD_TESTS: [(&str, &str); 3] = [
+    ("std/math.test.wal", include_str!("../std/math.test.wal")),
+    ("std/strings.test.wal", include_str!("../std/strings.test.wal")),
+    ("std/arrays.test.wal", include_str!("../std/arrays.test.wal")),
+];
+
+// native stack of the interpreter, part of it is kept free so stack overflow can be reported as error
+const STACK: usize = 1024 * 1024 * 1024;
+const STACK_RESERVE: usize = 4 * 1024 * 1024;
+
+// global variables and functions of module
+type Environment = (HashMap<String, Token>, HashMap<String, Token>);
+
 #[derive(Clone)]
 struct Word {
     content: String,
@@ -30,20 +195,31 @@ struct Word {
 }
 
 fn error(message: &str, line: usize, pos: usize) -> ! {
-    println!("[ERROR] {} at line {}, col {}.", message, line, pos);
+    eprintln!("[ERROR] {} at line {}, col {}.", message, line, pos);
     exit(-1);
 }
 
 fn is_numeric(target: &str) -> bool {
+    let target = target.strip_prefix('-').unwrap_or(target);
+    if target.is_empty() {
+        return false;
+    }
     for character in target.chars() {
-        if character < '0' || character > '9' {
+        if !character.is_ascii_digit() {
             return false;
         }
     }
-    return true;
+    true
 }
 
-fn lex(program: &String) -> Vec<Word> {
+fn is_float(target: &str) -> bool {
+    match target.split_once('.') {
+        Some((whole, fraction)) => is_numeric(whole) && !fraction.is_empty() && fraction.chars().all(|character| character.is_ascii_digit()),
+        None => false,
+    }
+}
+
+fn lex(program: &str) -> Vec<Word> {
     let mut result: Vec<Word> = Vec::new();
     let mut last = String::new();
     let mut line = 1;
@@ -97,423 +273,3510 @@ fn lex(program: &String) -> Vec<Word> {
             _ => last.push(character)
         }
     }
-    return result;
+    result
 }
 
 
-fn tokenize(program: Vec<Word>) -> Vec<Token> {
+fn tokenize(program: Vec<Word>) -> Result<Vec<Token>, Signal> {
     let mut tokens: Vec<Token> = Vec::new();
     let mut parsing_fn: bool = false;
     let mut rec_function: Vec<Word> = Vec::new();
-    let mut curent: Token = Token { kind: TokenKind::VOID, context: String::new(), body: Vec::new() };
+    let mut curent: Token = Token::new(TokenKind::VOID, String::new(), Vec::new());
     let mut nested: isize = 0;
-    for index in 0..program.len() {
-        let word = program[index].clone();
+    for word in program {
         if word.content == "[" {
             if parsing_fn {
                 nested += 1;
                 rec_function.push(word.clone());
             } else {
                 parsing_fn = true;
-                curent = Token { kind: TokenKind::FUNCTION, context: String::new(), body: Vec::new() }
+                curent = Token::new(TokenKind::FUNCTION, String::new(), Vec::new());
+                curent.line = word.line;
+                curent.pos = word.pos;
             }
         } else if parsing_fn {
             if rec_function.is_empty() {
                 if word.content.starts_with('\'') {
-                    curent.body.push(Token { kind: TokenKind::STRING, context: word.content[1..(word.content.len()-1)].to_string(), body: Vec::new() }) // TODO String trim
+                    Rc::make_mut(&mut curent.body).push(Token::new(TokenKind::STRING, word.content[1..(word.content.len()-1)].to_string(), Vec::new())) // TODO String trim
                 } else if is_numeric(&word.content) {
-                    curent.body.push(Token { kind: TokenKind::INT, context: word.content, body: Vec::new() })
+                    // values of integers are parsed wherever they are used, so they must fit
+                    let Ok(value) = word.content.parse::<isize>() else {
+                        return Err(Signal::Error(format!("Integer {} is out of range", word.content), word.line, word.pos));
+                    };
+                    // numbers are written the same way as computed ones, so equal values compare equal
+                    Rc::make_mut(&mut curent.body).push(Token::new(TokenKind::INT, format!("{}", value), Vec::new()))
+                } else if is_float(&word.content) {
+                    Rc::make_mut(&mut curent.body).push(Interpreter::float(word.content.parse::<f64>().unwrap()))
                 } else if curent.context.is_empty() {
                     curent.context = word.content;
                 } else if word.content == "]" {
                     tokens.push(curent.clone());
                     parsing_fn = false;
+                } else if word.content == "true" || word.content == "false" {
+                    Rc::make_mut(&mut curent.body).push(Token::new(TokenKind::BOOL, word.content, Vec::new()))
+                } else if word.content == "void" {
+                    Rc::make_mut(&mut curent.body).push(Token::new(TokenKind::VOID, String::new(), Vec::new()))
                 } else {
-                    curent.body.push(Token { kind: TokenKind::WORD, context: word.content, body: Vec::new() });
+                    Rc::make_mut(&mut curent.body).push(Token::new(TokenKind::WORD, word.content, Vec::new()));
                 }
             } else {
                 rec_function.push(word.clone());
                 if word.content == "]"{
                     nested -= 1;
                     if nested == 0 {
-                        curent.body.push(tokenize(rec_function)[0].clone());
+                        Rc::make_mut(&mut curent.body).push(tokenize(rec_function)?[0].clone());
                         rec_function = Vec::new();
                     }
                 }
             }
         } else {
-            error("Unexpected token", word.line, word.pos);
+            return Err(Signal::Error("Unexpected token".to_string(), word.line, word.pos));
         }
     }
 
-    return tokens;
+    Ok(tokens)
 }
 
 struct Interpreter {
-    variables: HashMap<String, Token>,
-//    functions: HashMap<String, Token>,
+    variables: Vec<HashMap<String, Token>>,
+    functions: HashMap<String, Token>,
+    call_stack: Vec<String>,
+    max_depth: usize,
+    // lowest address of native stack interpreter can use, 0 when the stack isn't limited
+    stack_end: usize,
+    tail_call: bool,
+    // generator whose body is running, None in the main thread
+    coroutine: Option<Rc<Coroutine>>,
+    abandoned: Rc<RefCell<Vec<Rc<Coroutine>>>>,
+    // module whose globals and functions are loaded, empty for the main script
+    module: String,
+    // globals and functions of modules which are not loaded right now
+    modules: HashMap<String, Environment>,
+    // names exported by evaluated modules, module in this map is never evaluated again
+    exports: HashMap<String, Vec<String>>,
+    importing: Vec<String>,
+    // ARGV of the script, every module gets it among its globals
+    arguments: Option<Token>,
+    // count of isolated evaluations, each gets its own environment
+    isolated: usize,
+    // macro functions and whether their last parameter takes rest of arguments
+    macros: HashMap<String, (Token, bool)>,
+    // count of generated names, so every expansion gets new ones
+    gensyms: usize,
+    // file which is evaluated, included files are resolved relative to it
+    file: PathBuf,
+    // output is buffered, it is flushed before reading input, writing errors and at exit
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    errors: Box<dyn Write>,
 }
 
 impl Interpreter {
 
     pub fn new() -> Interpreter {
+        Interpreter::with_streams(
+            Box::new(BufReader::new(std::io::stdin())),
+            Box::new(BufWriter::new(std::io::stdout())),
+            Box::new(std::io::stderr()),
+        )
+    }
+
+    // embedding hosts can read from and write to their own streams instead of stdio
+    pub fn with_streams(input: Box<dyn BufRead>, output: Box<dyn Write>, errors: Box<dyn Write>) -> Interpreter {
         Interpreter {
-            variables: HashMap::new(),
-//            functions: HashMap::new(),
+            variables: vec![HashMap::new()],
+            functions: HashMap::new(),
+            call_stack: Vec::new(),
+            max_depth: 10000,
+            stack_end: 0,
+            tail_call: false,
+            coroutine: None,
+            abandoned: Rc::new(RefCell::new(Vec::new())),
+            module: String::new(),
+            modules: HashMap::new(),
+            exports: HashMap::new(),
+            importing: Vec::new(),
+            arguments: None,
+            isolated: 0,
+            macros: HashMap::new(),
+            gensyms: 0,
+            file: PathBuf::new(),
+            input,
+            output,
+            errors,
         }
     }
 
-    fn to_number(&mut self, body: &Vec<Token>, function: &str) -> Vec<isize> {
-        let mut numbers: Vec<isize> = Vec::new();
-        for child in body {
-            let parsed = self.parse_token(child.clone());
-            if parsed.kind == TokenKind::INT {
-                numbers.push(parsed.context.parse::<isize>().unwrap());
-            } else {
-                error(&format!("Function {} takes only integer as argument", function), 0, 0);
-            }
-        }
+    fn write(&mut self, text: &str, error: bool) -> Result<(), Signal> {
+        let result = if error {
+            self.output.flush().and_then(|_| self.errors.write_all(text.as_bytes()))
+        } else {
+            self.output.write_all(text.as_bytes())
+        };
+        result.map_err(|error| self.raise(&format!("Unable to write output: {}", error)))
+    }
 
-        return numbers;
+    fn flush(&mut self) -> Result<(), Signal> {
+        self.output.flush().map_err(|error| self.raise(&format!("Unable to write output: {}", error)))
     }
 
-    fn parse_token(&mut self, token: Token) -> Token {
-        return match token.kind {
-            TokenKind::FUNCTION => 
-                match token.context.as_str() {
-                    "->" => {
-                        for child in token.body.clone() {
-                            let child = self.parse_token(child);
-                            if child.kind == TokenKind::ARRAY {
-                                print!("Array: ");
-                                for item in child.body {
-                                    print!("{} ", item.context);
-                                }
-                                println!("");
-                            } else if child.kind == TokenKind::WORD {
-                                error("Unexpected word", 0, 0);
-                            } else if child.kind != TokenKind::VOID {
-                                print!("{}", child.context);
-                            }
-                        }
-                        println!();
-                        return Token { kind: TokenKind::VOID, body: Vec::new(), context: String::new() };
-                    },
-                    "<-" => {
-                        if !token.body.is_empty() {
-                            print!("{}", &self.parse_token(token.body[0].clone()).context);
-                            std::io::stdout().flush().expect("ERROR");
-                        }
-                        let mut input: String = String::new();
-                        std::io::stdin().read_line(&mut input).unwrap();
-                        return Token { kind: TokenKind::STRING, context: input.trim().to_string(), body: Vec::new()};
-                    },
-                    "+" => {
-                        let numbers = self.to_number(&token.body, "+");
-                        let mut result: isize = numbers[0];
-                        for number in numbers[1..].into_iter() {
-                            result += number;
-                        }
-                        return Token { kind: TokenKind::INT, context: format!("{}", result), body: Vec::new() };
-                    },
-                    "-" => {
-                        let numbers = self.to_number(&token.body, "-");
-                        let mut result: isize = numbers[0];
-                        for number in numbers[1..].into_iter() {
-                            result -= number;
-                        }
-                        return Token { kind: TokenKind::INT, context: format!("{}", result), body: Vec::new() };
-                    },
-                    "*" => {
-                        let numbers = self.to_number(&token.body, "*");
-                        let mut result: isize = numbers[0];
-                        for number in numbers[1..].into_iter() {
-                            result *= number;
-                        }
-                        return Token { kind: TokenKind::INT, context: format!("{}", result), body: Vec::new() };                    },
-                    "/" => {
-                        let numbers = self.to_number(&token.body, "/");
-                        let mut result: isize = numbers[0];
-                        for number in numbers[1..].into_iter() {
-                            result /= number;
-                        }
-                        return Token { kind: TokenKind::INT, context: format!("{}", result), body: Vec::new() };
-                    },
-                    "%" => {
-                        let numbers = self.to_number(&token.body, "/");
-                        let mut result: isize = numbers[0];
-                        for number in numbers[1..].into_iter() {
-                            result %= number;
-                        }
-                        return Token { kind: TokenKind::INT, context: format!("{}", result), body: Vec::new() };
-                    },
+    // flushes output and writes error which ended the program to the error stream, returns exit code
+    fn finish(&mut self, result: Result<(), Signal>) -> i32 {
+        let (message, line, pos) = match self.flush().and(result) {
+            Ok(()) => return 0,
+            Err(Signal::Exit(code)) => return code,
+            Err(Signal::Break) => ("Function break used outside of loop".to_string(), 0, 0),
+            Err(Signal::Continue) => ("Function continue used outside of loop".to_string(), 0, 0),
+            Err(Signal::Return(_)) => ("Function return used outside of function".to_string(), 0, 0),
+            Err(Signal::TailCall(..)) | Err(Signal::Stop) => unreachable!(),
+            Err(Signal::Error(message, line, pos)) => (message, line, pos),
+        };
+        let report = format!("[ERROR] {} at line {}, col {}.\n", message, line, pos);
+        // stdio is used when the error stream itself failed
+        if self.errors.write_all(report.as_bytes()).and_then(|_| self.errors.flush()).is_err() {
+            eprint!("{}", report);
+        }
+        -1
+    }
 
-                    "=" => {
-                        if token.body.len() < 2 {
-                            error("Function == takes at least 2 arguments", 0, 0);
-                        }
-                        let mut result: bool = true;
-                        let mut last: Token = self.parse_token(token.body[0].clone());
-                        for index in 1..token.body.len() {
-                            let parsed: Token = self.parse_token(token.body[index].clone());
-                            if last.kind != parsed.kind {
-                                result = false;
-                                break;
-                            }
-                    
-                            if last.context != parsed.context {
-                                result = false;
-                                break;
-                            }
+    fn file_error(&self, action: &str, path: &str, error: std::io::Error) -> Signal {
+        self.raise(&format!("Unable to {} '{}': {}", action, path, error))
+    }
 
-                            last = parsed;
-                        }
-                        return Token { kind: TokenKind::BOOL, context: format!("{}", result), body: Vec::new() }
-                    },
-                    "<" => {
-                        if token.body.len() != 2 {
-                            error("Function < takes exactly 2 arguments", 0, 0);
-                        }
+    // line without its line ending, None at the end of input
+    fn read_line(&mut self) -> Result<Option<String>, Signal> {
+        self.flush()?;
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => {
+                if line.ends_with('\n') {
+                    line.pop();
+                    if line.ends_with('\r') {
+                        line.pop();
+                    }
+                }
+                Ok(Some(line))
+            },
+            Err(error) => Err(self.raise(&format!("Unable to read input: {}", error))),
+        }
+    }
 
-                        let numbers = self.to_number(&token.body, "<");
-                        let result = numbers[0] < numbers[1];
+    // address on native stack of the caller, stack grows down on all supported platforms
+    fn stack_position() -> usize {
+        let marker = 0u8;
+        std::hint::black_box(&marker) as *const u8 as usize
+    }
 
-                        return Token { kind: TokenKind::BOOL, context: format!("{}", result), body: Vec::new() }
-                    },
-                    ">" => {
-                        if token.body.len() != 2 {
-                            error("Function > takes exactly 2 arguments", 0, 0);
-                        }
+    // interpreter runs on thread with stack of given size, deeply nested code fails before the stack overflows
+    fn limit_stack(&mut self, size: usize) {
+        self.stack_end = Interpreter::stack_position().saturating_sub(size.saturating_sub(STACK_RESERVE));
+    }
 
-                        let numbers = self.to_number(&token.body, ">");
-                        let result = numbers[0] > numbers[1];
+    fn raise(&self, message: &str) -> Signal {
+        // position is filled in by parse_token of function which raised it
+        Signal::Error(message.to_string(), 0, 0)
+    }
 
-                        return Token { kind: TokenKind::BOOL, context: format!("{}", result), body: Vec::new() }                    
-                    },
-                    ">=" => {
-                        if token.body.len() != 2 {
-                            error("Function >= takes exactly 2 arguments", 0, 0);
-                        }
+    fn call_chain(&self) -> String {
+        // recursion is collapsed, otherwise the chain would have thousands of items
+        let mut chain: Vec<String> = Vec::new();
+        let mut index = 0;
+        while index < self.call_stack.len() {
+            let name = &self.call_stack[index];
+            let mut count = 1;
+            while index + count < self.call_stack.len() && &self.call_stack[index + count] == name {
+                count += 1;
+            }
+            if count > 1 {
+                chain.push(format!("{} (x{})", name, count));
+            } else {
+                chain.push(name.clone());
+            }
+            index += count;
+        }
+        chain.join(" -> ")
+    }
 
-                        let numbers = self.to_number(&token.body, ">=");
-                        let result = numbers[0] >= numbers[1];
+    fn get_variable(&self, name: &str) -> Option<&Token> {
+        self.variables.last().unwrap().get(name).or_else(|| self.variables[0].get(name))
+    }
 
-                        return Token { kind: TokenKind::BOOL, context: format!("{}", result), body: Vec::new() }                    
-                    },
-                    "<=" => {
-                        if token.body.len() != 2 {
-                            error("Function <= takes exactly 2 arguments", 0, 0);
-                        }
+    fn set_variable(&mut self, name: String, value: Token) {
+        // assigning to global from function scope updates the global, everything else is local
+        let frame = self.variables.len() - 1;
+        if !self.variables[frame].contains_key(&name) && self.variables[0].contains_key(&name) {
+            self.variables[0].insert(name, value);
+        } else {
+            self.variables[frame].insert(name, value);
+        }
+    }
 
-                        let numbers = self.to_number(&token.body, "<=");
-                        let result = numbers[0] <= numbers[1];
+    fn parse_numbers(&mut self, body: &[Token], function: &str) -> Result<Vec<Number>, Signal> {
+        let mut numbers: Vec<Number> = Vec::new();
+        for child in body {
+            let parsed = self.parse_token(child)?;
+            match Interpreter::number(&parsed) {
+                Some(number) => numbers.push(number),
+                None => return Err(self.raise(&format!("Function {} takes only numbers as argument", function))),
+            }
+        }
 
-                        return Token { kind: TokenKind::BOOL, context: format!("{}", result), body: Vec::new() }                    
-                    },
-                    "=<" => {
-                        if token.body.len() < 3 {
-                            error("Function if takes at least 3 arguments", 0, 0);
-                        }
-                        let condition: Token = self.parse_token(token.body[0].clone());
-                        if condition.context == "false" {
-                            return self.parse_token(token.body[2].clone());
-                        } else {
-                            return self.parse_token(token.body[1].clone());
-                        }
-                    },
-                    "$" => {
-                        if token.body.len() == 1 {
-                            if self.variables.contains_key(&token.body[0].context) {
-                                return self.variables.get(&token.body[0].context).unwrap().clone();
-                            } else {
-                                error("Undefined variable", 0, 0);
-                            }
-                        } else if token.body.len() == 2 {
-                            let value = self.parse_token(token.body[1].clone());
-                            self.variables.insert(token.body[0].context.clone(), value.clone());
-                            return value;
-                        } else {
-                            error("Function $ takes at least 1 argument", 0, 0);
-                        }
-                    },
-                    "@" => {
-                        return Token { kind: TokenKind::ARRAY, context: "Array".to_string(), body: token.body};
-                    },
-                    "@>" => {
-                        let mut result: Token = self.parse_token(token.body[0].clone());
-                        // TODO errors
-                        for item in token.body[1..].into_iter() {
-                            result.body.push(item.clone());
-                        }
-                        return result;
-                    },
-                    "@$" => {
-                        if token.body.len() < 2 {
-                            error("Function @$ takes at least 2 arguments", 0, 0)
-                        }
-                        
-                        let index = self.parse_token(token.body[1].clone());
-                        if index.kind != TokenKind::INT {
-                            error("Array can be indexed only with integer", 0, 0);
-                        }
-                        let mut array: Vec<Token> = self.parse_token(token.body[0].clone()).body;
-                        let index: usize = index.context.parse::<usize>().unwrap();
-                        
-                        if token.body.len() == 2 {
-                            if index > array.len() - 1 {
-                                error(&format!("Cannot index to position {}, because size of array is {}", index, array.len()), 0, 0)
-                            }
-                            return self.parse_token(array[index].clone());
-                        } else {
-                            array[index] = self.parse_token(token.body[2].clone());
-                            return Token { kind: TokenKind::ARRAY, context: "Array".to_string(), body: array }; 
-                        }
-                    },
-                    "$_" => {
-                        if token.body.len() != 1 {
-                            error("Function $_ takes exactly 1 argument!", 0, 0);
-                        }
-                        let variable = self.parse_token(token.body[0].clone()).context;
-                        let value = env::var(variable).unwrap();
-                        return Token { kind: TokenKind::STRING, context: value, body: Vec::new() };
-                    },
-                    "<>" => { 
-                        if token.body.len() < 3 {
-                            error("Function <> takes at least 3 arguments!", 0, 0);
-                        }
-                        
-                        let iterator = self.parse_token(token.body[1].clone());
-                     
-                        if iterator.kind != TokenKind::ARRAY {
-                            error("Argument 2 in function <> must be array!", 0, 0);
-                        }
+        Ok(numbers)
+    }
 
-                        let variable = token.body[0].context.clone();
+    fn number(token: &Token) -> Option<Number> {
+        match token.kind {
+            TokenKind::INT => Some(Number::Int(token.context.parse::<isize>().unwrap())),
+            TokenKind::FLOAT => Some(Number::Float(token.context.parse::<f64>().unwrap())),
+            _ => None,
+        }
+    }
 
-                        for item in iterator.body.clone() {
-                            let item = self.parse_token(item);
-                            self.variables.insert(variable.clone(), item);
-                            for statement in token.body[2..].into_iter() {
-                                self.parse_token(statement.clone());
-                            }
-                        }
-                        self.variables.remove(&variable);
-                        return iterator; 
-                    },
-                    "!" => {
-                        if token.body.len() != 1 {
-                            error("Function ! takes exactly 1 argument", 0, 0);
-                        }
+    // debug format keeps the decimal point, so 1.0 doesn't print as integer
+    fn float(value: f64) -> Token {
+        Token::new(TokenKind::FLOAT, format!("{:?}", value), Vec::new())
+    }
 
-                        let mut value = self.parse_token(token.body[0].clone());
-                        if value.kind != TokenKind::BOOL {
-                            error("Function ! takes only boolean arguments", 0, 0);
-                        }
+    fn arithmetic(&self, function: &str, numbers: &[Number]) -> Result<Token, Signal> {
+        if numbers.iter().all(|number| matches!(number, Number::Int(_))) {
+            let mut result: isize = match numbers[0] { Number::Int(value) => value, Number::Float(_) => unreachable!() };
+            for number in numbers[1..].iter() {
+                let number = match number { Number::Int(value) => *value, Number::Float(_) => unreachable!() };
+                if number == 0 && (function == "/" || function == "%") {
+                    return Err(self.raise("Division by zero"));
+                }
+                let next = match function {
+                    "+" => result.checked_add(number),
+                    "-" => result.checked_sub(number),
+                    "*" => result.checked_mul(number),
+                    "/" => result.checked_div(number),
+                    _ => result.checked_rem(number),
+                };
+                result = match next {
+                    Some(next) => next,
+                    None => return Err(self.raise(&format!("Integer overflow in function {}", function))),
+                };
+            }
+            return Ok(Token::new(TokenKind::INT, format!("{}", result), Vec::new()));
+        }
 
-                        value.context = (if value.context == "true" { "false" } else { "true" }).to_string();
-                        return value;
-                    },
-                    ".." => {
-                        // [.. 1 3]
-                        if token.body.len() != 2 {
-                            error("Function .. takes exactly 2 arguments", 0, 0);
-                        }
+        let mut result: f64 = numbers[0].float();
+        for number in numbers[1..].iter() {
+            match function {
+                "+" => result += number.float(),
+                "-" => result -= number.float(),
+                "*" => result *= number.float(),
+                "/" => result /= number.float(),
+                _ => result %= number.float(),
+            }
+        }
+        Ok(Interpreter::float(result))
+    }
 
-                        let from = self.parse_token(token.body[0].clone());
-                        let to = self.parse_token(token.body[1].clone());
+    fn type_name(kind: &TokenKind) -> &'static str {
+        match kind {
+            TokenKind::FUNCTION | TokenKind::LAMBDA => "function",
+            TokenKind::STRING => "string",
+            TokenKind::INT => "int",
+            TokenKind::FLOAT => "float",
+            TokenKind::VOID => "void",
+            TokenKind::BOOL => "bool",
+            TokenKind::WORD => "word",
+            TokenKind::ARRAY => "array",
+            TokenKind::RANGE => "range",
+            TokenKind::GENERATOR => "generator",
+            TokenKind::MAP => "map",
+            TokenKind::CODE => "code",
+        }
+    }
 
-                        if from.kind != TokenKind::INT || to.kind != TokenKind::INT {
-                            error("Arguments for function .. must be integers", 0, 0);
-                        }
+    // yield inside nested function, macro or quoted code doesn't belong to the function
+    fn yields(statements: &[Token]) -> bool {
+        statements.iter().any(|statement| statement.kind == TokenKind::FUNCTION
+            && (statement.context == "yield" || (!["&", "macro", "quote", "quasiquote"].contains(&statement.context.as_str()) && Interpreter::yields(&statement.body))))
+    }
 
-                        let from = from.context.parse::<isize>().unwrap();
-                        let to = to.context.parse::<isize>().unwrap();
+    fn arity(&self, token: &Token, min: usize, max: usize) -> Result<(), Signal> {
+        let count = token.body.len();
+        if count >= min && count <= max {
+            return Ok(());
+        }
 
-                        let mut result: Vec<Token> = Vec::new();
-                        for item in from..to+1 {
-                            result.push(Token { kind: TokenKind::INT, context: format!("{}", item), body: Vec::new()});
-                        }
+        let plural = if min == 1 { "argument" } else { "arguments" };
+        let message = if min == max {
+            format!("Function {} takes exactly {} {}", token.context, min, plural)
+        } else if max == usize::MAX {
+            format!("Function {} takes at least {} {}", token.context, min, plural)
+        } else {
+            format!("Function {} takes {} to {} arguments", token.context, min, max)
+        };
+        Err(self.raise(&message))
+    }
 
-                        return Token { kind: TokenKind::ARRAY, context: "Array".to_string(), body: result}
-                    },
-                    "><" => {
-                        if token.body.len() < 2 {
-                            error("Function >< takes at least 2 arguments!", 0, 0);
-                        }
-                        
+    fn integer(&mut self, token: &Token, index: usize) -> Result<isize, Signal> {
+        let value = self.parse_token(&token.body[index])?;
+        if value.kind != TokenKind::INT {
+            return Err(self.raise(&format!("Argument {} in function {} must be integer", index + 1, token.context)));
+        }
 
-                        while self.parse_token(token.body[0].clone()).context != "false" {
-                            for statement in token.body[1..].into_iter() {
-                                self.parse_token(statement.clone());
-                            }
-                        }
-                        
-                        return Token { kind: TokenKind::VOID, context: String::new(), body: Vec::new() };
-                    },
-                    "~" => {
-                        if token.body.len() != 1 {
-                            error("Function ~ takes exactly 1 argument", 0, 0);
-                        }
+        Ok(value.context.parse::<isize>().unwrap())
+    }
 
-                        let code: Token = self.parse_token(token.body[0].clone());
-                        if code.kind != TokenKind::STRING {
-                            error("Argument for function ~ must be string!", 0, 0);
-                        }
+    fn map(&mut self, token: &Token, index: usize) -> Result<Token, Signal> {
+        let value = self.parse_token(&token.body[index])?;
+        if value.kind != TokenKind::MAP {
+            return Err(self.raise(&format!("Argument {} in function {} must be map", index + 1, token.context)));
+        }
 
-                        self.parse(tokenize(lex(&code.context)));
-                        return code;
-                    },
-                    "o-o" => {
-                        for statement in token.body {
-                            self.parse_token(statement.clone());
-                        }
+        Ok(value)
+    }
 
-                        return Token { kind: TokenKind::VOID, context: String::new(), body: Vec::new() };
-                    },
-                    "~>" => {
-                        if token.body.len() != 1 {
-                            error("Function ~> takes exactly 1 argument", 0, 0);
-                        }
+    fn string(&mut self, token: &Token, index: usize) -> Result<String, Signal> {
+        let value = self.parse_token(&token.body[index])?;
+        if value.kind != TokenKind::STRING {
+            return Err(self.raise(&format!("Argument {} in function {} must be string", index + 1, token.context)));
+        }
 
-                        let file: Token = self.parse_token(token.body[0].clone());
-                        if file.kind != TokenKind::STRING {
-                            error("Argument for function ~> must be string!", 0, 0);
-                        }   
-        
-                        if !Path::new(&file.context).exists() {
-                            error(&format!("File {} not found", file.context), 0, 0);
-                        }
-                
-                        let code = fs::read_to_string(file.context)
-                            .expect("File is not readable");
+        Ok(value.context)
+    }
 
-                        self.parse(tokenize(lex(&code)));
-                        return Token { kind: TokenKind::VOID, context: String::new(), body: Vec::new() }; 
-                    },
-                    _ => error("Undefined function", 0, 0) // TODO position
-                },
-            TokenKind::INT|TokenKind::STRING|TokenKind::VOID|TokenKind::BOOL|TokenKind::WORD|TokenKind::ARRAY => token,
+    // evaluated items of any iterable value
+    fn items(&mut self, token: &Token, index: usize) -> Result<Vec<Token>, Signal> {
+        let value = self.parse_token(&token.body[index])?;
+        match value.kind {
+            TokenKind::ARRAY|TokenKind::RANGE|TokenKind::GENERATOR|TokenKind::MAP|TokenKind::STRING => self.collect(&value),
+            _ => Err(self.raise(&format!("Argument {} in function {} must be iterable", index + 1, token.context))),
         }
     }
 
-    fn parse(&mut self, program: Vec<Token>) {
-        for token in program {
-            self.parse_token(token);
+    // maps are equal when they have the same pairs in any order
+    fn equals(left: &Token, right: &Token) -> bool {
+        if left.kind != right.kind || left.context != right.context || left.body.len() != right.body.len() {
+            return false;
         }
+        if left.kind == TokenKind::MAP {
+            return left.body.iter().all(|pair| Interpreter::map_get(right, &pair.body[0]).is_some_and(|value| Interpreter::equals(&pair.body[1], value)));
+        }
+        left.body.iter().zip(right.body.iter()).all(|(left, right)| Interpreter::equals(left, right))
     }
-}
 
-fn main() {
+    // removes . and resolves .. without touching filesystem
+    fn normalize(path: &str) -> String {
+        let mut result = PathBuf::new();
+        let mut depth = 0;
+        for component in Path::new(path).components() {
+            match component {
+                Component::CurDir => {},
+                Component::ParentDir if depth > 0 => {
+                    result.pop();
+                    depth -= 1;
+                },
+                // .. can't go above root
+                Component::ParentDir if result.has_root() => {},
+                Component::ParentDir => result.push(".."),
+                Component::Normal(name) => {
+                    result.push(name);
+                    depth += 1;
+                },
+                component => result.push(component.as_os_str()),
+            }
+        }
+
+        if result.as_os_str().is_empty() {
+            return ".".to_string();
+        }
+        result.to_string_lossy().into_owned()
+    }
 
-    let mut args = env::args();
-    let filename = args.nth(1).unwrap();
-    if !Path::new(&filename).exists() {
-        error(&format!("File {} not found", filename), 0, 0);
+    // * and ? don't match leading dot, so hidden files are matched only explicitly
+    fn wildcard(pattern: &[char], name: &[char]) -> bool {
+        match pattern.first() {
+            None => name.is_empty(),
+            Some('*') => (0..=name.len()).any(|skip| Interpreter::wildcard(&pattern[1..], &name[skip..])),
+            Some('?') => !name.is_empty() && Interpreter::wildcard(&pattern[1..], &name[1..]),
+            Some('[') if pattern.len() > 2 && pattern[2..].contains(&']') => {
+                // [abc], [a-z] or negated [!abc]
+                let end = 2 + pattern[2..].iter().position(|character| *character == ']').unwrap();
+                let negated = pattern[1] == '!' || pattern[1] == '^';
+                let class = &pattern[if negated { 2 } else { 1 }..end];
+                let Some(character) = name.first() else { return false };
+                let mut matched = false;
+                let mut index = 0;
+                while index < class.len() {
+                    if index + 2 < class.len() && class[index + 1] == '-' {
+                        matched |= class[index] <= *character && *character <= class[index + 2];
+                        index += 3;
+                    } else {
+                        matched |= class[index] == *character;
+                        index += 1;
+                    }
+                }
+                matched != negated && Interpreter::wildcard(&pattern[end + 1..], &name[1..])
+            },
+            Some(character) => name.first() == Some(character) && Interpreter::wildcard(&pattern[1..], &name[1..]),
+        }
     }
 
-    let code = fs::read_to_string(filename)
-        .expect("File is not readable");
+    // walks only directories which can match, ** matches any number of directories
+    fn glob(path: &Path, components: &[&str], results: &mut Vec<String>) {
+        let Some((component, rest)) = components.split_first() else {
+            if !path.as_os_str().is_empty() {
+                results.push(path.to_string_lossy().into_owned());
+            }
+            return;
+        };
 
-    let mut interpreter: Interpreter = Interpreter::new();
-    interpreter.parse(tokenize(lex(&code)));
+        if !component.contains(['*', '?', '[']) {
+            let next = path.join(component);
+            if (rest.is_empty() && next.symlink_metadata().is_ok()) || next.is_dir() {
+                Interpreter::glob(&next, rest, results);
+            }
+            return;
+        }
+
+        let directory = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+        let Ok(entries) = fs::read_dir(directory) else { return };
+        let pattern: Vec<char> = component.chars().collect();
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') && !component.starts_with('.') {
+                continue;
+            }
+            let next = path.join(&name);
+            if *component == "**" {
+                // symlinks are not followed, so links to parent directory don't loop
+                if entry.file_type().map(|kind| kind.is_dir()).unwrap_or(false) {
+                    Interpreter::glob(&next, components, results);
+                } else if rest.is_empty() {
+                    results.push(next.to_string_lossy().into_owned());
+                }
+            } else if Interpreter::wildcard(&pattern, &name.chars().collect::<Vec<char>>()) && (rest.is_empty() || next.is_dir()) {
+                Interpreter::glob(&next, rest, results);
+            }
+        }
+        if *component == "**" {
+            Interpreter::glob(path, rest, results);
+        }
+    }
+
+    // negative indexes count from the end, result is clamped to the length
+    fn slice_bounds(start: isize, end: isize, length: usize) -> (usize, usize) {
+        let clamp = |index: isize| if index < 0 {
+            (length as isize + index).max(0) as usize
+        } else {
+            (index as usize).min(length)
+        };
+        let (start, end) = (clamp(start), clamp(end));
+        (start, end.max(start))
+    }
+
+    // computed in i128, so ranges over the whole isize don't overflow, None when length doesn't fit in usize
+    fn range_length(range: &Token) -> Option<usize> {
+        let (from, to, step) = Interpreter::range_bounds(range);
+        usize::try_from((to as i128 - from as i128) / step as i128 + 1).ok()
+    }
+
+    // length of range which is indexed, too long range can't be indexed from the end
+    fn indexed_range_length(&self, range: &Token) -> Result<usize, Signal> {
+        match Interpreter::range_length(range) {
+            Some(length) => Ok(length),
+            None => Err(self.raise("Range is too long to be indexed")),
+        }
+    }
+
+    // item of range at index lower than its length
+    fn range_item(range: &Token, index: usize) -> Token {
+        let (from, _, step) = Interpreter::range_bounds(range);
+        let item = from as i128 + index as i128 * step as i128;
+        Token::new(TokenKind::INT, format!("{}", item), Vec::new())
+    }
+
+    // index of value in range, computed without going through its items
+    fn range_position(range: &Token, value: &Token) -> Option<usize> {
+        if value.kind != TokenKind::INT {
+            return None;
+        }
+        let (from, to, step) = Interpreter::range_bounds(range);
+        let (from, to, step) = (from as i128, to as i128, step as i128);
+        let offset = value.context.parse::<i128>().ok()? - from;
+        if offset % step != 0 || offset / step < 0 || offset / step > (to - from) / step {
+            return None;
+        }
+        usize::try_from(offset / step).ok()
+    }
+
+    fn range_last(range: &Token) -> isize {
+        let (from, to, step) = Interpreter::range_bounds(range);
+        let (from, to, step) = (from as i128, to as i128, step as i128);
+        (from + (to - from) / step * step) as isize
+    }
+
+    // variable is moved out of its frame while it's modified, so its collection doesn't have to be cloned
+    fn modify_variable<T>(&mut self, token: &Token, modify: impl FnOnce(&mut Interpreter, &mut Token) -> Result<T, Signal>) -> Result<T, Signal> {
+        if token.body[0].kind != TokenKind::WORD {
+            return Err(self.raise(&format!("Argument 1 in function {} must be variable name", token.context)));
+        }
+
+        let name = &token.body[0].context;
+        let last = self.variables.len() - 1;
+        let frame = if self.variables[last].contains_key(name) {
+            last
+        } else if self.variables[0].contains_key(name) {
+            0
+        } else {
+            return Err(self.raise(&format!("Undefined variable {}", name)));
+        };
+
+        let mut value = self.variables[frame].remove(name).unwrap();
+        let result = modify(self, &mut value);
+        self.variables[frame].insert(name.clone(), value);
+        result
+    }
+
+    // negative index counts from the end, index equal to length is allowed only when inserting
+    fn resolve_index(&self, index: &Token, length: usize, inserting: bool, kind: &TokenKind) -> Result<usize, Signal> {
+        let name = format!("{:?}", kind).to_lowercase();
+        if index.kind != TokenKind::INT {
+            return Err(self.raise(&format!("{}{} can be indexed only with integer", name[..1].to_uppercase(), &name[1..])));
+        }
+
+        let position = index.context.parse::<isize>().unwrap();
+        let resolved = if position < 0 { length as i128 + position as i128 } else { position as i128 };
+        let limit = if inserting { length as i128 + 1 } else { length as i128 };
+        if resolved < 0 || resolved >= limit {
+            return Err(self.raise(&format!("Cannot index to position {}, because size of {} is {}", position, name, length)));
+        }
+
+        Ok(resolved as usize)
+    }
+
+    // ranges are lazy, they have to become arrays before changing them
+    fn force(&mut self, value: &mut Token) -> Result<(), Signal> {
+        if value.kind == TokenKind::RANGE {
+            *value = self.materialize(value.clone())?;
+        }
+
+        Ok(())
+    }
+
+    fn replace_character(&self, text: &Token, index: &Token, value: &Token) -> Result<Token, Signal> {
+        if value.kind != TokenKind::STRING {
+            return Err(self.raise("Only string can be stored in string"));
+        }
+
+        let mut characters: Vec<String> = text.context.chars().map(String::from).collect();
+        let index = self.resolve_index(index, characters.len(), false, &text.kind)?;
+        characters[index] = value.context.clone();
+        Ok(Token::new(TokenKind::STRING, characters.concat(), Vec::new()))
+    }
+
+    fn set_path(&mut self, target: &mut Token, path: &[Token], value: Token) -> Result<(), Signal> {
+        self.force(target)?;
+        if target.kind == TokenKind::STRING && path.len() == 1 {
+            *target = self.replace_character(target, &path[0], &value)?;
+            return Ok(());
+        }
+
+        if target.kind == TokenKind::MAP {
+            if path.len() == 1 {
+                Interpreter::map_set(target, path[0].clone(), value);
+                return Ok(());
+            }
+
+            return match Rc::make_mut(&mut target.body).iter_mut().find(|pair| Interpreter::equals(&pair.body[0], &path[0])) {
+                Some(pair) => self.set_path(&mut Rc::make_mut(&mut pair.body)[1], &path[1..], value),
+                None => Err(self.raise(&format!("Key {} not found in map", path[0].context))),
+            };
+        }
+
+        if target.kind != TokenKind::ARRAY {
+            return Err(self.raise(&format!("Value of kind {:?} can't be indexed", target.kind)));
+        }
+
+        let index = self.resolve_index(&path[0], target.body.len(), false, &target.kind)?;
+        if path.len() == 1 {
+            Rc::make_mut(&mut target.body)[index] = value;
+            return Ok(());
+        }
+        self.set_path(&mut Rc::make_mut(&mut target.body)[index], &path[1..], value)
+    }
+
+    fn range_bounds(range: &Token) -> (isize, isize, isize) {
+        let bounds: Vec<isize> = range.body.iter().map(|bound| bound.context.parse::<isize>().unwrap()).collect();
+        (bounds[0], bounds[1], bounds[2])
+    }
+
+    // every iterable value goes through here, so <> and collection functions work with all of them
+    fn iterate(&mut self, iterable: &Token, mut consumer: Consumer) -> Result<(), Signal> {
+        match iterable.kind {
+            TokenKind::ARRAY => {
+                for item in iterable.body.clone().iter() {
+                    let item = self.parse_token(item)?;
+                    consumer(self, item)?;
+                }
+            },
+            TokenKind::STRING => {
+                for character in iterable.context.chars() {
+                    consumer(self, Token::new(TokenKind::STRING, character.to_string(), Vec::new()))?;
+                }
+            },
+            TokenKind::RANGE => {
+                let (from, to, step) = Interpreter::range_bounds(iterable);
+                let mut current = from;
+                while (step > 0 && current <= to) || (step < 0 && current >= to) {
+                    consumer(self, Token::new(TokenKind::INT, format!("{}", current), Vec::new()))?;
+                    // range can end at the limit of isize, next step would overflow
+                    current = match current.checked_add(step) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                }
+            },
+            TokenKind::MAP => {
+                for pair in iterable.body.clone().iter() {
+                    consumer(self, pair.clone())?;
+                }
+            },
+            TokenKind::GENERATOR if iterable.body.first().is_none_or(|function| function.kind != TokenKind::LAMBDA) => {
+                // built in generators read lines only when the loop asks for them
+                if iterable.context == "read-lines" {
+                    while let Some(line) = self.read_line()? {
+                        consumer(self, Token::new(TokenKind::STRING, line, Vec::new()))?;
+                    }
+                } else {
+                    self.run_lines(iterable, consumer)?;
+                }
+            },
+            TokenKind::GENERATOR => {
+                // generator continues where previous iteration stopped
+                while let Some(item) = self.resume(iterable)? {
+                    consumer(self, item)?;
+                }
+            },
+            _ => return Err(self.raise(&format!("Value of kind {:?} is not iterable", iterable.kind))),
+        }
+
+        Ok(())
+    }
+
+    // command from array of program and its arguments and map of options input, env and cwd
+    fn command(&mut self, arguments: &Token, options: Option<&Token>) -> Result<(Command, Option<String>), Signal> {
+        let mut words: Vec<String> = Vec::new();
+        for argument in self.collect(arguments)? {
+            if argument.kind == TokenKind::WORD {
+                return Err(self.raise("Unexpected word"));
+            }
+            words.push(self.display(&argument)?);
+        }
+        if words.is_empty() {
+            return Err(self.raise("Command can't be empty"));
+        }
+
+        let mut command = Command::new(&words[0]);
+        command.args(&words[1..]);
+        let mut input = None;
+        if let Some(options) = options {
+            for pair in options.body.iter() {
+                let (key, value) = (&pair.body[0], &pair.body[1]);
+                match (key.context.as_str(), &value.kind) {
+                    ("input", TokenKind::STRING) => input = Some(value.context.clone()),
+                    ("cwd", TokenKind::STRING) => { command.current_dir(&value.context); },
+                    ("env", TokenKind::MAP) => {
+                        for variable in value.body.iter() {
+                            let name = self.display(&variable.body[0])?;
+                            let content = self.display(&variable.body[1])?;
+                            command.env(name, content);
+                        }
+                    },
+                    _ => {
+                        let key = self.debug(key)?;
+                        return Err(self.raise(&format!("Invalid option {} of command", key)));
+                    },
+                }
+            }
+        }
+
+        Ok((command, input))
+    }
+
+    fn spawn(&mut self, command: &mut Command, input: Option<String>, output: Stdio) -> Result<std::process::Child, Signal> {
+        // output of wal has to be written before the child writes to the same terminal
+        self.flush()?;
+        let program = command.get_program().to_string_lossy().into_owned();
+        let mut child = match command.stdin(if input.is_some() { Stdio::piped() } else { Stdio::null() }).stdout(output).spawn() {
+            Ok(child) => child,
+            Err(error) => return Err(self.raise(&format!("Unable to run '{}': {}", program, error))),
+        };
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            // written from another thread, so big input and output don't block each other
+            std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        }
+
+        Ok(child)
+    }
+
+    fn run_lines(&mut self, generator: &Token, mut consumer: Consumer) -> Result<(), Signal> {
+        let (mut command, input) = self.command(&generator.body[0], generator.body.get(1))?;
+        let mut child = self.spawn(&mut command, input, Stdio::piped())?;
+        let program = command.get_program().to_string_lossy().into_owned();
+        let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+        let result = loop {
+            match lines.next() {
+                Some(Ok(line)) => if let Err(signal) = consumer(self, Token::new(TokenKind::STRING, line, Vec::new())) {
+                    break Err(signal);
+                },
+                Some(Err(error)) => break Err(self.raise(&format!("Unable to read output of '{}': {}", program, error))),
+                None => break Ok(()),
+            }
+        };
+
+        if result.is_err() {
+            // loop ended early, nobody would read rest of the output
+            let _ = child.kill();
+        }
+        let status = child.wait();
+        result?;
+        match status {
+            Ok(status) if status.success() => Ok(()),
+            Ok(status) => Err(self.raise(&format!("Command '{}' failed with {}", program, status))),
+            Err(error) => Err(self.raise(&format!("Unable to run '{}': {}", program, error))),
+        }
+    }
+
+    // spec is map with program name, flags and positionals as maps of name and help, options as maps of name and map with help and default
+    fn parse_arguments(&mut self, spec: &Token, arguments: &[String]) -> Result<Token, Signal> {
+        let key = |name: &str| Token::new(TokenKind::STRING, name.to_string(), Vec::new());
+        let empty = Token::new(TokenKind::MAP, "Map".to_string(), Vec::new());
+        for pair in spec.body.iter() {
+            if !["program", "flags", "options", "positionals"].contains(&pair.body[0].context.as_str()) || pair.body[0].kind != TokenKind::STRING {
+                let name = self.debug(&pair.body[0])?;
+                return Err(self.raise(&format!("Invalid key {} in argument spec", name)));
+            }
+        }
+        let program = match Interpreter::map_get(spec, &key("program")) {
+            Some(program) if program.kind == TokenKind::STRING => program.context.clone(),
+            Some(_) => return Err(self.raise("Value of program in argument spec must be string")),
+            None => "script".to_string(),
+        };
+        let mut sections: Vec<Token> = Vec::new();
+        for section in ["flags", "options", "positionals"] {
+            let value = Interpreter::map_get(spec, &key(section)).cloned().unwrap_or_else(|| empty.clone());
+            if value.kind != TokenKind::MAP {
+                return Err(self.raise(&format!("Value of {} in argument spec must be map", section)));
+            }
+            sections.push(value);
+        }
+        let (flags, options, positionals) = (&sections[0], &sections[1], &sections[2]);
+        for (section, values) in [("flags", flags), ("options", options), ("positionals", positionals)] {
+            for pair in values.body.iter() {
+                let (name, value) = (&pair.body[0], &pair.body[1]);
+                if name.kind != TokenKind::STRING {
+                    let name = self.debug(name)?;
+                    return Err(self.raise(&format!("Name {} in {} of argument spec must be string", name, section)));
+                }
+                if name.context == "help" {
+                    return Err(self.raise(&format!("Name help in {} of argument spec is reserved for --help", section)));
+                }
+                if section != "options" {
+                    if value.kind != TokenKind::STRING {
+                        return Err(self.raise(&format!("Help of {} in argument spec must be string", name.context)));
+                    }
+                    continue;
+                }
+                if value.kind != TokenKind::MAP {
+                    return Err(self.raise(&format!("Settings of option {} in argument spec must be map", name.context)));
+                }
+                for setting in value.body.iter() {
+                    match (setting.body[0].context.as_str(), &setting.body[0].kind, &setting.body[1].kind) {
+                        ("help", TokenKind::STRING, TokenKind::STRING) | ("default", TokenKind::STRING, _) => {},
+                        ("help", TokenKind::STRING, _) => return Err(self.raise(&format!("Help of option {} in argument spec must be string", name.context))),
+                        _ => {
+                            let setting = self.debug(&setting.body[0])?;
+                            return Err(self.raise(&format!("Invalid setting {} of option {} in argument spec", setting, name.context)));
+                        },
+                    }
+                }
+            }
+        }
+
+        // usage line and help of every argument
+        let mut usage = format!("Usage: {}", program);
+        let mut help: Vec<(String, String)> = Vec::new();
+        for flag in flags.body.iter() {
+            usage.push_str(&format!(" [--{}]", flag.body[0].context));
+            help.push((format!("--{}", flag.body[0].context), flag.body[1].context.clone()));
+        }
+        for option in options.body.iter() {
+            let (name, settings) = (&option.body[0].context, &option.body[1]);
+            let text = Interpreter::map_get(settings, &key("help")).map(|help| help.context.clone()).unwrap_or_default();
+            match Interpreter::map_get(settings, &key("default")) {
+                Some(default) => {
+                    usage.push_str(&format!(" [--{} <{}>]", name, name));
+                    let default = self.display(&default.clone())?;
+                    help.push((format!("--{} <{}>", name, name), format!("{} (default: {})", text, default).trim_start().to_string()));
+                },
+                None => {
+                    usage.push_str(&format!(" --{} <{}>", name, name));
+                    help.push((format!("--{} <{}>", name, name), text));
+                },
+            }
+        }
+        for positional in positionals.body.iter() {
+            usage.push_str(&format!(" <{}>", positional.body[0].context));
+            help.push((positional.body[0].context.clone(), positional.body[1].context.clone()));
+        }
+        help.push(("--help".to_string(), "show this help".to_string()));
+        let width = help.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+        for (name, text) in help {
+            usage.push_str(&format!("\n  {:width$}  {}", name, text, width = width));
+        }
+        let fail = |interpreter: &Interpreter, message: String| interpreter.raise(&format!("{}\n\n{}", message, usage));
+
+        // help is void unless --help was given
+        let mut result = Token::new(TokenKind::MAP, "Map".to_string(), Vec::new());
+        Interpreter::map_set(&mut result, key("help"), Token::new(TokenKind::VOID, String::new(), Vec::new()));
+        for flag in flags.body.iter() {
+            Interpreter::map_set(&mut result, flag.body[0].clone(), Token::new(TokenKind::BOOL, "false".to_string(), Vec::new()));
+        }
+        let mut values: Vec<String> = Vec::new();
+        let mut index = 0;
+        let mut only_positionals = false;
+        while index < arguments.len() {
+            let argument = &arguments[index];
+            index += 1;
+            let Some(name) = argument.strip_prefix("--").filter(|_| !only_positionals) else {
+                values.push(argument.clone());
+                continue;
+            };
+            if name.is_empty() {
+                only_positionals = true;
+                continue;
+            }
+            if name == "help" {
+                // rest of arguments isn't checked, script only prints the usage
+                let mut result = Token::new(TokenKind::MAP, "Map".to_string(), Vec::new());
+                Interpreter::map_set(&mut result, key("help"), Token::new(TokenKind::STRING, usage, Vec::new()));
+                return Ok(result);
+            }
+
+            let (name, inline) = match name.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (name, None),
+            };
+            if Interpreter::map_get(flags, &key(name)).is_some() {
+                if inline.is_some() {
+                    return Err(fail(self, format!("Flag --{} doesn't take value", name)));
+                }
+                Interpreter::map_set(&mut result, key(name), Token::new(TokenKind::BOOL, "true".to_string(), Vec::new()));
+            } else if let Some(settings) = Interpreter::map_get(options, &key(name)) {
+                let value = match inline {
+                    Some(value) => value,
+                    None if index < arguments.len() => {
+                        index += 1;
+                        arguments[index - 1].clone()
+                    },
+                    None => return Err(fail(self, format!("Option --{} requires value", name))),
+                };
+                // value gets kind of the default
+                let value = match Interpreter::map_get(settings, &key("default")).map(|default| &default.kind) {
+                    Some(TokenKind::INT) => match value.parse::<isize>() {
+                        Ok(number) => Token::new(TokenKind::INT, format!("{}", number), Vec::new()),
+                        Err(_) => return Err(fail(self, format!("Option --{} must be integer, got '{}'", name, value))),
+                    },
+                    Some(TokenKind::FLOAT) => match value.parse::<f64>() {
+                        Ok(number) => Interpreter::float(number),
+                        Err(_) => return Err(fail(self, format!("Option --{} must be number, got '{}'", name, value))),
+                    },
+                    _ => Token::new(TokenKind::STRING, value, Vec::new()),
+                };
+                Interpreter::map_set(&mut result, key(name), value);
+            } else {
+                return Err(fail(self, format!("Unknown option --{}", name)));
+            }
+        }
+
+        for option in options.body.iter() {
+            if Interpreter::map_get(&result, &option.body[0]).is_none() {
+                match Interpreter::map_get(&option.body[1], &key("default")) {
+                    Some(default) => Interpreter::map_set(&mut result, option.body[0].clone(), default.clone()),
+                    None => return Err(fail(self, format!("Missing option --{}", option.body[0].context))),
+                }
+            }
+        }
+        if values.len() > positionals.body.len() {
+            return Err(fail(self, format!("Unexpected argument '{}'", values[positionals.body.len()])));
+        }
+        if values.len() < positionals.body.len() {
+            return Err(fail(self, format!("Missing argument {}", positionals.body[values.len()].body[0].context)));
+        }
+        for (positional, value) in positionals.body.iter().zip(values) {
+            Interpreter::map_set(&mut result, positional.body[0].clone(), Token::new(TokenKind::STRING, value, Vec::new()));
+        }
+
+        Ok(result)
+    }
+
+    fn collect(&mut self, iterable: &Token) -> Result<Vec<Token>, Signal> {
+        let items: Rc<RefCell<Vec<Token>>> = Rc::new(RefCell::new(Vec::new()));
+        let target = items.clone();
+        self.iterate(iterable, Box::new(move |_, item| {
+            target.borrow_mut().push(item);
+            Ok(())
+        }))?;
+
+        Ok(items.take())
+    }
+
+    fn materialize(&mut self, value: Token) -> Result<Token, Signal> {
+        if value.kind != TokenKind::RANGE {
+            return Ok(value);
+        }
+
+        Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), self.collect(&value)?))
+    }
+
+    // how value is shown to user, strings are shown as they are
+    fn display(&mut self, value: &Token) -> Result<String, Signal> {
+        match value.kind {
+            TokenKind::STRING | TokenKind::INT | TokenKind::FLOAT | TokenKind::BOOL => Ok(value.context.clone()),
+            TokenKind::VOID => Ok(String::new()),
+            TokenKind::CODE => self.source(&value.body[0]),
+            _ => self.debug(value),
+        }
+    }
+
+    // representation of value in wal syntax, nested values included
+    fn debug(&mut self, value: &Token) -> Result<String, Signal> {
+        match value.kind {
+            TokenKind::FUNCTION => {
+                let value = self.parse_token(value)?;
+                self.debug(&value)
+            },
+            TokenKind::STRING => Ok(format!("'{}'", value.context)),
+            TokenKind::INT | TokenKind::BOOL | TokenKind::WORD => Ok(value.context.clone()),
+            TokenKind::FLOAT => Ok(Interpreter::float_source(value)),
+            TokenKind::VOID => Ok("void".to_string()),
+            TokenKind::ARRAY => {
+                let mut result = "[@".to_string();
+                for item in value.body.iter() {
+                    result.push(' ');
+                    result.push_str(&self.debug(item)?);
+                }
+                Ok(result + "]")
+            },
+            TokenKind::MAP => {
+                let mut result = "[@@".to_string();
+                for pair in value.body.iter() {
+                    result.push_str(&format!(" {} {}", self.debug(&pair.body[0])?, self.debug(&pair.body[1])?));
+                }
+                Ok(result + "]")
+            },
+            TokenKind::RANGE => {
+                let (from, to, step) = Interpreter::range_bounds(value);
+                if step.abs() == 1 {
+                    Ok(format!("[.. {} {}]", from, to))
+                } else {
+                    Ok(format!("[.. {} {} {}]", from, to, step.abs()))
+                }
+            },
+            TokenKind::LAMBDA => Ok(format!("<function {}>", value.context)),
+            TokenKind::GENERATOR => Ok(format!("<generator {}>", value.context)),
+            TokenKind::CODE => Ok(format!("[quote {}]", self.source(&value.body[0])?)),
+        }
+    }
+
+    // floats which are not written as literal are written as expression with the same value
+    fn float_source(value: &Token) -> String {
+        let number = value.context.parse::<f64>().unwrap();
+        if number.is_nan() {
+            "[/ 0.0 0.0]".to_string()
+        } else if number.is_infinite() {
+            format!("[/ {:?} 0.0]", number.signum())
+        } else if value.context.contains('e') {
+            // display writes all digits instead of exponent
+            let text = format!("{}", number);
+            if text.contains('.') { text } else { text + ".0" }
+        } else {
+            value.context.clone()
+        }
+    }
+
+    // code as it would be written in wal
+    fn source(&mut self, code: &Token) -> Result<String, Signal> {
+        match code.kind {
+            TokenKind::FUNCTION => {
+                let mut result = format!("[{}", code.context);
+                for argument in code.body.iter() {
+                    result.push(' ');
+                    result.push_str(&self.source(argument)?);
+                }
+                Ok(result + "]")
+            },
+            TokenKind::WORD => Ok(code.context.clone()),
+            _ => self.debug(code),
+        }
+    }
+
+    // token of code from value, code values are unwrapped and other values become literals
+    fn unquote(value: &Token) -> Token {
+        if value.kind == TokenKind::CODE {
+            return value.body[0].clone();
+        }
+        value.clone()
+    }
+
+    // replaces calls of macros by code they return, quoted code is left as it is
+    fn expand(&mut self, token: &Token) -> Result<Token, Signal> {
+        if self.macros.is_empty() || token.kind != TokenKind::FUNCTION || token.context == "quote" || token.context == "quasiquote" {
+            return Ok(token.clone());
+        }
+
+        if let Some((function, rest)) = self.macros.get(&token.context).cloned() {
+            let count = function.body[0].body.len();
+            let mut arguments: Vec<Token> = token.body.iter().map(|argument| Interpreter::quote(argument.clone())).collect();
+            if rest {
+                if arguments.len() < count - 1 {
+                    return Err(self.raise(&format!("Macro {} takes at least {} arguments", token.context, count - 1)));
+                }
+                let rest = arguments.split_off(count - 1);
+                arguments.push(Token::new(TokenKind::ARRAY, "Array".to_string(), rest));
+            }
+
+            let mut expanded = match self.call(function, arguments) {
+                Ok(value) => Interpreter::unquote(&value),
+                Err(Signal::Error(message, 0, 0)) if token.line != 0 => return Err(Signal::Error(message, token.line, token.pos)),
+                Err(signal) => return Err(signal),
+            };
+            if expanded.line == 0 {
+                (expanded.line, expanded.pos) = (token.line, token.pos);
+            }
+            // macro can return call of another macro, even itself
+            if self.call_stack.len() >= self.max_depth {
+                return Err(self.raise(&format!("Stack overflow, maximum depth of {} expansions exceeded in {}", self.max_depth, self.call_chain())));
+            }
+            self.call_stack.push(format!("macro {}", token.context));
+            let result = self.expand(&expanded);
+            self.call_stack.pop();
+            return match result {
+                Err(Signal::Error(message, 0, 0)) if token.line != 0 => Err(Signal::Error(message, token.line, token.pos)),
+                result => result,
+            };
+        }
+
+        let mut expanded = token.clone();
+        let mut body: Vec<Token> = Vec::new();
+        for argument in token.body.iter() {
+            body.push(self.expand(argument)?);
+        }
+        expanded.body = Rc::new(body);
+        Ok(expanded)
+    }
+
+    // names which template binds with $, <>, try or as parameters
+    fn binders(template: &Token, names: &mut Vec<String>) {
+        if template.kind != TokenKind::FUNCTION || template.context == "unquote" || template.context == "unquote-splice" {
+            return;
+        }
+
+        let mut bound: Vec<&Token> = Vec::new();
+        match template.context.as_str() {
+            "$" if template.body.len() == 2 => bound.push(&template.body[0]),
+            "<>" if !template.body.is_empty() => bound.push(&template.body[0]),
+            "try" if template.body.len() > 1 => bound.push(&template.body[1]),
+            "&" | "macro" => {
+                if let Some(parameters) = template.body.iter().take(2).find(|argument| argument.kind == TokenKind::FUNCTION && argument.context == "@") {
+                    bound.extend(parameters.body.iter());
+                }
+            },
+            _ => {},
+        }
+        for name in bound {
+            if name.kind == TokenKind::WORD && !names.contains(&name.context) {
+                names.push(name.context.clone());
+            }
+        }
+        for argument in template.body.iter() {
+            Interpreter::binders(argument, names);
+        }
+    }
+
+    // copy of template with unquoted parts evaluated, names bound by template are renamed so they can't clash with names of caller
+    fn quasiquote(&mut self, template: &Token, renames: &HashMap<String, String>) -> Result<Token, Signal> {
+        match template.kind {
+            TokenKind::WORD => {
+                let mut word = template.clone();
+                if let Some(name) = renames.get(&word.context) {
+                    word.context = name.clone();
+                }
+                Ok(word)
+            },
+            TokenKind::FUNCTION if template.context == "unquote" => {
+                if template.body.len() != 1 {
+                    return Err(self.raise("Function unquote takes exactly 1 argument"));
+                }
+                let value = self.parse_token(&template.body[0])?;
+                Ok(Interpreter::unquote(&value))
+            },
+            TokenKind::FUNCTION => {
+                let mut result = template.clone();
+                let mut body: Vec<Token> = Vec::new();
+                for argument in template.body.iter() {
+                    if argument.kind == TokenKind::FUNCTION && argument.context == "unquote-splice" {
+                        if argument.body.len() != 1 {
+                            return Err(self.raise("Function unquote-splice takes exactly 1 argument"));
+                        }
+                        let values = self.parse_token(&argument.body[0])?;
+                        for value in self.collect(&values)? {
+                            body.push(Interpreter::unquote(&value));
+                        }
+                    } else {
+                        body.push(self.quasiquote(argument, renames)?);
+                    }
+                }
+                result.body = Rc::new(body);
+                Ok(result)
+            },
+            _ => Ok(template.clone()),
+        }
+    }
+
+    fn gensym(&mut self, name: &str) -> String {
+        self.gensyms += 1;
+        format!("{}~{}", name, self.gensyms)
+    }
+
+    fn quote(code: Token) -> Token {
+        Token::new(TokenKind::CODE, "Code".to_string(), vec![code])
+    }
+
+    // {} takes next argument, {1} argument by position and {name} key of map argument or variable
+    // when template has named placeholders, map arguments only supply names and are left out of positions
+    fn format(&mut self, template: &str, arguments: &[Token]) -> Result<String, Signal> {
+        // text before each placeholder and the placeholder, last text has none
+        let mut pieces: Vec<(String, Option<String>)> = Vec::new();
+        let mut text = String::new();
+        let mut characters = template.chars().peekable();
+        while let Some(character) = characters.next() {
+            match character {
+                '{' if characters.peek() == Some(&'{') => {
+                    characters.next();
+                    text.push('{');
+                },
+                '}' if characters.peek() == Some(&'}') => {
+                    characters.next();
+                    text.push('}');
+                },
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match characters.next() {
+                            Some('}') => break,
+                            Some(character) => placeholder.push(character),
+                            None => return Err(self.raise("Unclosed placeholder in format string")),
+                        }
+                    }
+                    pieces.push((std::mem::take(&mut text), Some(placeholder)));
+                },
+                '}' => return Err(self.raise("Unmatched } in format string")),
+                character => text.push(character),
+            }
+        }
+        pieces.push((text, None));
+
+        let named = pieces.iter().filter_map(|(_, placeholder)| placeholder.as_ref())
+            .any(|placeholder| {
+                let name = placeholder.split_once(':').map_or(placeholder.as_str(), |(name, _)| name);
+                !name.is_empty() && name.parse::<usize>().is_err()
+            });
+        let (maps, positional): (Vec<Token>, Vec<Token>) = arguments.iter().cloned().partition(|argument| named && argument.kind == TokenKind::MAP);
+
+        let mut result = String::new();
+        let mut next = 0;
+        for (text, placeholder) in pieces {
+            result.push_str(&text);
+            let Some(placeholder) = placeholder else { continue };
+            let (name, specification) = placeholder.split_once(':').unwrap_or((&placeholder, ""));
+            let value = if name.is_empty() {
+                next += 1;
+                positional.get(next - 1).cloned()
+            } else if let Ok(index) = name.parse::<usize>() {
+                positional.get(index).cloned()
+            } else {
+                let key = Token::new(TokenKind::STRING, name.to_string(), Vec::new());
+                maps.iter()
+                    .find_map(|map| Interpreter::map_get(map, &key).cloned())
+                    .or_else(|| self.get_variable(name).cloned())
+            };
+            let value = match value {
+                Some(value) => value,
+                None if name.is_empty() => return Err(self.raise(&format!("Missing argument for placeholder {}", next))),
+                None => return Err(self.raise(&format!("Missing argument for placeholder {}", name))),
+            };
+            result.push_str(&self.format_value(&value, specification)?);
+        }
+
+        Ok(result)
+    }
+
+    // too long result is error instead of overflow or running out of memory
+    fn repeated(&self, text: &str, count: usize) -> Result<String, Signal> {
+        let mut result = String::new();
+        match text.len().checked_mul(count) {
+            Some(size) if result.try_reserve_exact(size).is_ok() => {},
+            _ => return Err(self.raise(&format!("Text repeated {} times is too long", count))),
+        }
+        for _ in 0..count {
+            result.push_str(text);
+        }
+        Ok(result)
+    }
+
+    // specification is [[fill]align][0][width][.precision][?] where align is <, ^ or >
+    fn format_value(&mut self, value: &Token, specification: &str) -> Result<String, Signal> {
+        let characters: Vec<char> = specification.chars().collect();
+        let is_align = |character: Option<&char>| matches!(character, Some('<') | Some('^') | Some('>'));
+        let mut index = 0;
+        let (mut fill, mut align) = (' ', None);
+        if is_align(characters.get(1)) {
+            (fill, align) = (characters[0], Some(characters[1]));
+            index = 2;
+        } else if is_align(characters.first()) {
+            align = Some(characters[0]);
+            index = 1;
+        }
+
+        let zero = characters.get(index) == Some(&'0');
+        if zero {
+            index += 1;
+        }
+        let invalid = || self.raise(&format!("Invalid format specification '{}'", specification));
+        let number = |index: &mut usize| -> Result<usize, Signal> {
+            let mut number: usize = 0;
+            while let Some(digit) = characters.get(*index).and_then(|character| character.to_digit(10)) {
+                number = number.checked_mul(10).and_then(|number| number.checked_add(digit as usize)).ok_or_else(invalid)?;
+                *index += 1;
+            }
+            Ok(number)
+        };
+        let width = number(&mut index)?;
+        let mut precision = None;
+        if characters.get(index) == Some(&'.') {
+            index += 1;
+            let digits = number(&mut index)?;
+            // rust formats floats only with precision which fits in u16
+            if digits > u16::MAX as usize {
+                return Err(invalid());
+            }
+            precision = Some(digits);
+        }
+        let debug = characters.get(index) == Some(&'?');
+        if debug {
+            index += 1;
+        }
+        if index != characters.len() {
+            return Err(invalid());
+        }
+
+        let numeric = value.kind == TokenKind::INT || value.kind == TokenKind::FLOAT;
+        let mut text = match (precision, &value.kind) {
+            (Some(precision), TokenKind::FLOAT) => format!("{:.*}", precision, value.context.parse::<f64>().unwrap()),
+            (Some(precision), TokenKind::STRING) if !debug => value.context.chars().take(precision).collect(),
+            _ if debug => self.debug(value)?,
+            _ => self.display(value)?,
+        };
+
+        let missing = width.saturating_sub(text.chars().count());
+        if missing == 0 {
+            return Ok(text);
+        }
+        if zero && numeric && align.is_none() {
+            // zeros go after the sign
+            let sign = if text.starts_with('-') { text.remove(0).to_string() } else { String::new() };
+            return Ok(sign + &self.repeated("0", missing)? + &text);
+        }
+
+        let fill = fill.to_string();
+        Ok(match align.unwrap_or(if numeric { '>' } else { '<' }) {
+            '<' => text + &self.repeated(&fill, missing)?,
+            '>' => self.repeated(&fill, missing)? + &text,
+            _ => self.repeated(&fill, missing / 2)? + &text + &self.repeated(&fill, missing - missing / 2)?,
+        })
+    }
+
+    fn parse_block(&mut self, statements: &[Token]) -> Result<Token, Signal> {
+        let mut result = Token::new(TokenKind::VOID, String::new(), Vec::new());
+        for statement in statements {
+            result = self.parse_token(statement)?;
+        }
+
+        Ok(result)
+    }
+
+    // loads globals and functions of module and returns module which was loaded before
+    fn switch_module(&mut self, module: &str) -> String {
+        if module == self.module {
+            return module.to_string();
+        }
+
+        let globals = std::mem::take(&mut self.variables[0]);
+        let functions = std::mem::take(&mut self.functions);
+        let previous = std::mem::replace(&mut self.module, module.to_string());
+        self.modules.insert(previous.clone(), (globals, functions));
+        let (globals, functions) = self.modules.remove(module).unwrap_or_default();
+        self.variables[0] = globals;
+        self.functions = functions;
+        previous
+    }
+
+    // path relative to the current file, then to directories from WAL_PATH
+    fn resolve(&self, path: &str) -> Result<PathBuf, Signal> {
+        let mut tried: Vec<PathBuf> = Vec::new();
+        if Path::new(path).is_absolute() {
+            tried.push(PathBuf::from(path));
+        } else {
+            tried.push(self.file.parent().unwrap_or(Path::new("")).join(path));
+            if let Some(directories) = env::var_os("WAL_PATH") {
+                tried.extend(env::split_paths(&directories).filter(|directory| !directory.as_os_str().is_empty()).map(|directory| directory.join(path)));
+            }
+        }
+
+        match tried.iter().find(|candidate| candidate.is_file()) {
+            Some(found) => Ok(found.clone()),
+            None => {
+                let tried: Vec<String> = tried.iter().map(|candidate| candidate.to_string_lossy().into_owned()).collect();
+                Err(self.raise(&format!("File {} not found, tried {}", path, tried.join(", "))))
+            },
+        }
+    }
+
+    // main script starts the chain of imports, so modules can't import it back
+    fn enter_main(&mut self, file: &str) {
+        self.file = PathBuf::from(file);
+        if let Ok(path) = fs::canonicalize(file) {
+            self.importing.push(path.to_string_lossy().into_owned());
+        }
+    }
+
+    fn set_arguments(&mut self, arguments: Vec<String>) {
+        let arguments = arguments.into_iter().map(|argument| Token::new(TokenKind::STRING, argument, Vec::new())).collect();
+        let arguments = Token::new(TokenKind::ARRAY, "Array".to_string(), arguments);
+        self.variables[0].insert("ARGV".to_string(), arguments.clone());
+        self.arguments = Some(arguments);
+    }
+
+    fn evaluate_file(&mut self, file: PathBuf) -> Result<(), Signal> {
+        let code = match fs::read_to_string(&file) {
+            Ok(code) => code,
+            Err(error) => return Err(self.file_error("read file", &file.to_string_lossy(), error)),
+        };
+        self.evaluate_code(file, &code)
+    }
+
+    // the file is current while its code runs
+    fn evaluate_code(&mut self, file: PathBuf, code: &str) -> Result<(), Signal> {
+        let previous = std::mem::replace(&mut self.file, file);
+        let result = tokenize(lex(code)).and_then(|program| self.parse(program));
+        self.file = previous;
+        result
+    }
+
+    // runs every function which name starts with test- and returns number of passed and failed tests
+    fn run_tests(&mut self, name: &str) -> Result<(usize, usize), Signal> {
+        let mut tests: Vec<String> = self.functions.keys().filter(|function| function.starts_with("test-")).cloned().collect();
+        tests.sort();
+        let (mut passed, mut failed) = (0, 0);
+        for test in tests {
+            let function = self.functions[&test].clone();
+            match self.call(function, Vec::new()) {
+                Ok(_) => {
+                    passed += 1;
+                    self.write(&format!("ok {} {}\n", name, test), false)?;
+                },
+                Err(Signal::Error(message, line, pos)) => {
+                    failed += 1;
+                    self.write(&format!("FAILED {} {}: {} at line {}, col {}.\n", name, test, message, line, pos), false)?;
+                },
+                Err(signal) => return Err(signal),
+            }
+        }
+
+        Ok((passed, failed))
+    }
+
+    // evaluates module once in its own globals and binds its exports as namespace.name
+    fn import(&mut self, path: &str, namespace: &str) -> Result<(), Signal> {
+        // standard library is imported by name, without looking at filesystem
+        let library = STD.iter().find(|(name, _)| *name == path.trim_end_matches(".wal"));
+        let module = match library {
+            Some((name, _)) => name.to_string(),
+            None => match fs::canonicalize(self.resolve(path)?) {
+                Ok(module) => module.to_string_lossy().into_owned(),
+                Err(error) => return Err(self.file_error("import", path, error)),
+            },
+        };
+        if let Some(index) = self.importing.iter().position(|importing| *importing == module) {
+            let mut cycle = self.importing[index..].to_vec();
+            cycle.push(module);
+            return Err(self.raise(&format!("Import cycle {}", cycle.join(" -> "))));
+        }
+
+        if !self.exports.contains_key(&module) {
+            self.importing.push(module.clone());
+            let previous = self.switch_module(&module);
+            // module doesn't see local variables of function which imports it
+            let frames = self.variables.split_off(1);
+            if let Some(arguments) = &self.arguments {
+                self.variables[0].insert("ARGV".to_string(), arguments.clone());
+            }
+            self.exports.insert(module.clone(), Vec::new());
+            let result = match library {
+                Some((_, code)) => self.evaluate_code(PathBuf::from(&module), code),
+                None => self.evaluate_file(PathBuf::from(&module)),
+            };
+            self.variables.extend(frames);
+            self.switch_module(&previous);
+            self.importing.pop();
+            if let Err(signal) = result {
+                self.exports.remove(&module);
+                return Err(signal);
+            }
+        }
+
+        let (globals, functions) = match self.modules.get(&module) {
+            Some(environment) => environment.clone(),
+            None => (HashMap::new(), HashMap::new()),
+        };
+        for name in self.exports[&module].clone() {
+            let bound = format!("{}.{}", namespace, name);
+            if let Some(function) = functions.get(&name) {
+                self.functions.insert(bound, function.clone());
+            } else if let Some(value) = globals.get(&name) {
+                self.variables[0].insert(bound, value.clone());
+            } else {
+                return Err(self.raise(&format!("Module {} doesn't define exported name {}", path, name)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn call(&mut self, function: Token, arguments: Vec<Token>) -> Result<Token, Signal> {
+        if self.call_stack.len() >= self.max_depth {
+            return Err(self.raise(&format!("Stack overflow, maximum depth of {} calls exceeded in {}", self.max_depth, self.call_chain())));
+        }
+
+        self.call_stack.push(function.context.clone());
+        let mut function = function;
+        let mut arguments = arguments;
+        // tail calls replace current call instead of recursing, so they run in constant stack
+        let result = loop {
+            let parameters = &function.body[0].body;
+            if parameters.len() != arguments.len() {
+                break Err(self.raise(&format!("Function {} takes exactly {} arguments", function.context, parameters.len())));
+            }
+
+            // function runs with globals and functions of module where it was created
+            let module = self.switch_module(&function.body[1].context.clone());
+            // frame starts with variables captured when the function was created
+            let mut frame: HashMap<String, Token> = function.body[1].body.iter()
+                .map(|pair| (pair.body[0].context.clone(), pair.body[1].clone()))
+                .collect();
+            frame.extend(parameters.iter().map(|parameter| parameter.context.clone()).zip(arguments));
+            self.variables.push(frame);
+            let file = std::mem::replace(&mut self.file, PathBuf::from(&function.body[2].context));
+            let result = match function.body[3..].split_last() {
+                Some((last, statements)) => self.parse_block(statements).and_then(|_| self.parse_tail(last)),
+                None => Ok(Token::new(TokenKind::VOID, String::new(), Vec::new())),
+            };
+            self.file = file;
+            self.variables.pop();
+            self.switch_module(&module);
+
+            match result {
+                Err(Signal::TailCall(next, next_arguments)) => {
+                    *self.call_stack.last_mut().unwrap() = next.context.clone();
+                    function = next;
+                    arguments = next_arguments;
+                },
+                Err(Signal::Return(value)) => break Ok(value),
+                Err(Signal::Break) => break Err(self.raise("Function break used outside of loop")),
+                Err(Signal::Continue) => break Err(self.raise("Function continue used outside of loop")),
+                result => break result,
+            }
+        };
+        self.call_stack.pop();
+        result
+    }
+
+    // calls function value, generator functions only create generator which runs when iterated
+    fn apply(&mut self, function: Token, arguments: Vec<Token>) -> Result<Token, Signal> {
+        if function.body[0].context == "Generator" {
+            let name = function.context.clone();
+            let context = Context { module: function.body[1].context.clone(), ..Context::default() };
+            let mut body = vec![function];
+            body.extend(arguments);
+            let mut generator = Token::new(TokenKind::GENERATOR, name, body);
+            generator.coroutine = Some(Rc::new(Coroutine {
+                context: RefCell::new(context),
+                step: RefCell::new(None),
+                state: Cell::new(CoroutineState::Created),
+                resume: RefCell::new(None),
+                steps: RefCell::new(None),
+                abandoned: Some(self.abandoned.clone()),
+            }));
+            return Ok(generator);
+        }
+
+        self.call(function, arguments)
+    }
+
+    // exchanges what runs on the current thread with context stored in generator
+    fn swap_context(&mut self, context: &mut Context) {
+        let frames = self.variables.split_off(1);
+        self.variables.append(&mut context.frames);
+        context.frames = frames;
+        std::mem::swap(&mut self.call_stack, &mut context.call_stack);
+        let module = std::mem::take(&mut context.module);
+        context.module = self.switch_module(&module);
+        std::mem::swap(&mut self.file, &mut context.file);
+        std::mem::swap(&mut self.stack_end, &mut context.stack_end);
+    }
+
+    // runs generator until its next yield, None when its body has ended
+    fn resume(&mut self, generator: &Token) -> Result<Option<Token>, Signal> {
+        let coroutine = generator.coroutine.clone().unwrap();
+        match coroutine.state.get() {
+            CoroutineState::Finished => Ok(None),
+            CoroutineState::Running => Err(self.raise(&format!("Generator {} can't be iterated from its own body", generator.context))),
+            _ => self.hand_over(&coroutine, Some(generator), true),
+        }
+    }
+
+    // gives interpreter to generator thread and waits until it yields or ends
+    fn hand_over(&mut self, coroutine: &Rc<Coroutine>, generator: Option<&Token>, resume: bool) -> Result<Option<Token>, Signal> {
+        let parent = self.coroutine.replace(coroutine.clone());
+        self.swap_context(&mut coroutine.context.borrow_mut());
+        let created = coroutine.state.replace(CoroutineState::Running) == CoroutineState::Created;
+        if created {
+            let (resume, resumed) = channel();
+            let (stepped, steps) = channel();
+            coroutine.resume.replace(Some(resume));
+            coroutine.steps.replace(Some(steps));
+            let generator = generator.unwrap();
+            let task = Handover((self as *mut Interpreter, generator.body[0].clone(), generator.body[1..].to_vec()));
+            let spawned = std::thread::Builder::new().stack_size(STACK).spawn(move || {
+                let (interpreter, function, arguments) = task.take();
+                // SAFETY: consumer waits for the generator thread since this point until it yields or ends
+                let interpreter = unsafe { &mut *interpreter };
+                HANDOVER.with(|handover| handover.replace(Some((stepped, resumed))));
+                interpreter.limit_stack(STACK);
+                let result = interpreter.call(function, arguments);
+                interpreter.yield_step(result.map(|_| None));
+            });
+            if let Err(error) = spawned {
+                self.swap_context(&mut coroutine.context.borrow_mut());
+                self.coroutine = parent;
+                coroutine.state.set(CoroutineState::Finished);
+                return Err(self.raise(&format!("Unable to start generator: {}", error)));
+            }
+        } else {
+            coroutine.resume.borrow().as_ref().unwrap().send(resume).expect("generator thread stopped");
+        }
+
+        coroutine.steps.borrow().as_ref().unwrap().recv().expect("generator thread stopped");
+        self.coroutine = parent;
+        let step = coroutine.step.take().unwrap();
+        coroutine.state.set(if matches!(step, Ok(Some(_))) { CoroutineState::Suspended } else { CoroutineState::Finished });
+        step
+    }
+
+    // called on generator thread, gives interpreter back to the consumer
+    fn yield_step(&mut self, step: Result<Option<Token>, Signal>) {
+        let coroutine = self.coroutine.clone().unwrap();
+        coroutine.step.replace(Some(step));
+        self.swap_context(&mut coroutine.context.borrow_mut());
+        drop(coroutine);
+        HANDOVER.with(|handover| handover.borrow().as_ref().unwrap().0.send(()).expect("consumer of generator stopped"));
+    }
+
+    // generators which were dropped while suspended end their bodies, so their threads finish
+    fn stop_abandoned(&mut self) {
+        loop {
+            let coroutine = self.abandoned.borrow_mut().pop();
+            let Some(coroutine) = coroutine else { break };
+            let _ = self.hand_over(&coroutine, None, false);
+        }
+    }
+
+    // function given as argument, either name of defined function or function value
+    fn callable(&mut self, token: &Token, index: usize) -> Result<Token, Signal> {
+        let value = self.parse_token(&token.body[index])?;
+        match value.kind {
+            TokenKind::LAMBDA => Ok(value),
+            TokenKind::WORD => match self.functions.get(&value.context) {
+                Some(function) => Ok(function.clone()),
+                None => Err(self.raise(&format!("Undefined function {}", value.context))),
+            },
+            _ => Err(self.raise(&format!("Argument {} in function {} must be function", index + 1, token.context))),
+        }
+    }
+
+    fn compare(&self, left: &Token, right: &Token) -> Result<Ordering, Signal> {
+        match (&left.kind, &right.kind) {
+            (TokenKind::INT|TokenKind::FLOAT, TokenKind::INT|TokenKind::FLOAT) => Interpreter::number(left).unwrap().partial_cmp(&Interpreter::number(right).unwrap())
+                .ok_or_else(|| self.raise("NaN can't be compared")),
+            (TokenKind::STRING, TokenKind::STRING) | (TokenKind::BOOL, TokenKind::BOOL) => Ok(left.context.cmp(&right.context)),
+            _ => Err(self.raise(&format!("Values of kind {:?} and {:?} can't be compared", left.kind, right.kind))),
+        }
+    }
+
+    fn map_get<'a>(map: &'a Token, key: &Token) -> Option<&'a Token> {
+        map.body.iter().find(|pair| Interpreter::equals(&pair.body[0], key)).map(|pair| &pair.body[1])
+    }
+
+    fn map_set(map: &mut Token, key: Token, value: Token) {
+        match Rc::make_mut(&mut map.body).iter_mut().find(|pair| Interpreter::equals(&pair.body[0], &key)) {
+            Some(pair) => Rc::make_mut(&mut pair.body)[1] = value,
+            None => Rc::make_mut(&mut map.body).push(Token::new(TokenKind::ARRAY, "Array".to_string(), vec![key, value])),
+        }
+    }
+
+    fn parse_tail(&mut self, token: &Token) -> Result<Token, Signal> {
+        if token.kind != TokenKind::FUNCTION {
+            return self.parse_token(token);
+        }
+
+        match token.context.as_str() {
+            "=<" if token.body.len() >= 3 => {
+                let condition: Token = self.parse_token(&token.body[0])?;
+                if condition.context == "false" {
+                    self.parse_tail(&token.body[2])
+                } else {
+                    self.parse_tail(&token.body[1])
+                }
+            },
+            "return" if token.body.len() == 1 => self.parse_tail(&token.body[0]),
+            _ => {
+                self.tail_call = true;
+                self.parse_token(token)
+            },
+        }
+    }
+
+    fn parse_token(&mut self, token: &Token) -> Result<Token, Signal> {
+        let (line, pos) = (token.line, token.pos);
+        if !self.abandoned.borrow().is_empty() {
+            self.stop_abandoned();
+        }
+        if Interpreter::stack_position() < self.stack_end {
+            let chain = if self.call_stack.is_empty() { String::new() } else { format!(" in {}", self.call_chain()) };
+            return Err(Signal::Error(format!("Stack overflow, code is nested too deep{}", chain), line, pos));
+        }
+        match self.evaluate(token) {
+            Err(Signal::Error(message, 0, 0)) if line != 0 => Err(Signal::Error(message, line, pos)),
+            result => result,
+        }
+    }
+
+    #[allow(clippy::needless_return)]
+    fn evaluate(&mut self, token: &Token) -> Result<Token, Signal> {
+        let tail_call = std::mem::take(&mut self.tail_call);
+        match token.kind {
+            TokenKind::FUNCTION => 
+                match token.context.as_str() {
+                    "->" | "print" | "eprint" | "eprintln" => {
+                        // -> and eprintln end with new line, print and eprint don't
+                        let mut output = String::new();
+                        for child in token.body.iter() {
+                            let child = self.parse_token(child)?;
+                            if child.kind == TokenKind::WORD {
+                                return Err(self.raise("Unexpected word"));
+                            }
+                            output.push_str(&self.display(&child)?);
+                        }
+                        if token.context == "->" || token.context == "eprintln" {
+                            output.push('\n');
+                        }
+
+                        self.write(&output, token.context.starts_with('e'))?;
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "repr" => {
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        return Ok(Token::new(TokenKind::STRING, self.debug(&value)?, Vec::new()));
+                    },
+                    "<-" => {
+                        if !token.body.is_empty() {
+                            let prompt = self.parse_token(&token.body[0])?;
+                            self.write(&prompt.context, false)?;
+                        }
+                        // end of input is void, so it can be told apart from empty line
+                        return match self.read_line()? {
+                            Some(input) => Ok(Token::new(TokenKind::STRING, input.trim().to_string(), Vec::new())),
+                            None => Ok(Token::new(TokenKind::VOID, String::new(), Vec::new())),
+                        };
+                    },
+                    "read-all" | "read-bytes" => {
+                        // [read-bytes n] reads at most n bytes, both return void at the end of input
+                        if token.context == "read-all" {
+                            self.arity(token, 0, 0)?;
+                        } else {
+                            self.arity(token, 1, 1)?;
+                        }
+                        let limit = if token.body.is_empty() { u64::MAX } else { self.integer(token, 0)?.max(0) as u64 };
+                        self.flush()?;
+                        let mut buffer: Vec<u8> = Vec::new();
+                        if let Err(error) = self.input.by_ref().take(limit).read_to_end(&mut buffer) {
+                            return Err(self.raise(&format!("Unable to read input: {}", error)));
+                        }
+                        if buffer.is_empty() && limit > 0 {
+                            return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                        }
+                        return Ok(Token::new(TokenKind::STRING, String::from_utf8_lossy(&buffer).into_owned(), Vec::new()));
+                    },
+                    "read-lines" => {
+                        self.arity(token, 0, 0)?;
+                        return Ok(Token::new(TokenKind::GENERATOR, "read-lines".to_string(), Vec::new()));
+                    },
+                    "+" | "-" | "*" | "/" | "%" => {
+                        self.arity(token, 1, usize::MAX)?;
+                        let numbers = self.parse_numbers(&token.body, &token.context)?;
+                        return self.arithmetic(&token.context, &numbers);
+                    },
+
+                    "=" => {
+                        if token.body.len() < 2 {
+                            return Err(self.raise("Function == takes at least 2 arguments"));
+                        }
+                        let mut result: bool = true;
+                        let mut last: Token = self.parse_token(&token.body[0])?;
+                        for index in 1..token.body.len() {
+                            let parsed: Token = self.parse_token(&token.body[index])?;
+                            if !Interpreter::equals(&last, &parsed) {
+                                result = false;
+                                break;
+                            }
+
+                            last = parsed;
+                        }
+                        return Ok(Token::new(TokenKind::BOOL, format!("{}", result), Vec::new()))
+                    },
+                    "<" => {
+                        if token.body.len() != 2 {
+                            return Err(self.raise("Function < takes exactly 2 arguments"));
+                        }
+
+                        let numbers = self.parse_numbers(&token.body, "<")?;
+                        let result = numbers[0] < numbers[1];
+
+                        return Ok(Token::new(TokenKind::BOOL, format!("{}", result), Vec::new()))
+                    },
+                    ">" => {
+                        if token.body.len() != 2 {
+                            return Err(self.raise("Function > takes exactly 2 arguments"));
+                        }
+
+                        let numbers = self.parse_numbers(&token.body, ">")?;
+                        let result = numbers[0] > numbers[1];
+
+                        return Ok(Token::new(TokenKind::BOOL, format!("{}", result), Vec::new()))                    
+                    },
+                    ">=" => {
+                        if token.body.len() != 2 {
+                            return Err(self.raise("Function >= takes exactly 2 arguments"));
+                        }
+
+                        let numbers = self.parse_numbers(&token.body, ">=")?;
+                        let result = numbers[0] >= numbers[1];
+
+                        return Ok(Token::new(TokenKind::BOOL, format!("{}", result), Vec::new()))                    
+                    },
+                    "<=" => {
+                        if token.body.len() != 2 {
+                            return Err(self.raise("Function <= takes exactly 2 arguments"));
+                        }
+
+                        let numbers = self.parse_numbers(&token.body, "<=")?;
+                        let result = numbers[0] <= numbers[1];
+
+                        return Ok(Token::new(TokenKind::BOOL, format!("{}", result), Vec::new()))                    
+                    },
+                    "=<" => {
+                        if token.body.len() < 3 {
+                            return Err(self.raise("Function if takes at least 3 arguments"));
+                        }
+                        let condition: Token = self.parse_token(&token.body[0])?;
+                        if condition.context == "false" {
+                            return self.parse_token(&token.body[2]);
+                        } else {
+                            return self.parse_token(&token.body[1]);
+                        }
+                    },
+                    "$" => {
+                        if token.body.len() == 1 {
+                            if let Some(value) = self.get_variable(&token.body[0].context) {
+                                return Ok(value.clone());
+                            } else {
+                                return Err(self.raise("Undefined variable"));
+                            }
+                        } else if token.body.len() == 2 {
+                            let value = self.parse_token(&token.body[1])?;
+                            self.set_variable(token.body[0].context.clone(), value.clone());
+                            return Ok(value);
+                        } else {
+                            return Err(self.raise("Function $ takes at least 1 argument"));
+                        }
+                    },
+                    "@" => {
+                        // items are evaluated right away, so the array doesn't depend on variables of scope where it was created
+                        let mut items: Vec<Token> = Vec::new();
+                        for item in token.body.iter() {
+                            items.push(self.parse_token(item)?);
+                        }
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), items));
+                    },
+                    "@@" => {
+                        // [@@ key value key value...]
+                        if !token.body.len().is_multiple_of(2) {
+                            return Err(self.raise("Function @@ takes pairs of key and value"));
+                        }
+
+                        let mut map = Token::new(TokenKind::MAP, "Map".to_string(), Vec::new());
+                        for pair in token.body.chunks(2) {
+                            let key = self.parse_token(&pair[0])?;
+                            let value = self.parse_token(&pair[1])?;
+                            Interpreter::map_set(&mut map, key, value);
+                        }
+                        return Ok(map);
+                    },
+                    "@>" => {
+                        let value = self.parse_token(&token.body[0])?;
+                        let mut result: Token = self.materialize(value)?;
+                        // TODO errors
+                        for item in token.body[1..].iter() {
+                            let item = self.parse_token(item)?;
+                            Rc::make_mut(&mut result.body).push(item);
+                        }
+                        return Ok(result);
+                    },
+                    "@$" => {
+                        self.arity(token, 2, 3)?;
+
+                        let value = self.parse_token(&token.body[0])?;
+                        let index = self.parse_token(&token.body[1])?;
+                        if value.kind == TokenKind::MAP {
+                            if token.body.len() == 2 {
+                                match Interpreter::map_get(&value, &index) {
+                                    Some(item) => return Ok(item.clone()),
+                                    None => return Err(self.raise(&format!("Key {} not found in map", index.context))),
+                                }
+                            }
+
+                            let mut map = value;
+                            let item = self.parse_token(&token.body[2])?;
+                            Interpreter::map_set(&mut map, index, item);
+                            return Ok(map);
+                        }
+
+                        if value.kind != TokenKind::ARRAY && value.kind != TokenKind::RANGE && value.kind != TokenKind::STRING {
+                            return Err(self.raise(&format!("Value of kind {:?} can't be indexed", value.kind)));
+                        }
+
+                        if index.kind == TokenKind::RANGE {
+                            // [@$ array [.. 1 3]] returns items on positions from the range
+                            if token.body.len() == 3 {
+                                return Err(self.raise("Function @$ can't set items using range"));
+                            }
+
+                            let length = match value.kind {
+                                TokenKind::STRING => value.context.chars().count(),
+                                TokenKind::RANGE => self.indexed_range_length(&value)?,
+                                _ => value.body.len(),
+                            };
+                            let mut positions: Vec<usize> = Vec::new();
+                            for position in self.collect(&index)? {
+                                positions.push(self.resolve_index(&position, length, false, &value.kind)?);
+                            }
+
+                            if value.kind == TokenKind::RANGE {
+                                let items = positions.into_iter().map(|position| Interpreter::range_item(&value, position)).collect();
+                                return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), items));
+                            }
+                            if value.kind == TokenKind::STRING {
+                                let characters: Vec<char> = value.context.chars().collect();
+                                return Ok(Token::new(TokenKind::STRING, positions.iter().map(|position| characters[*position]).collect(), Vec::new()));
+                            }
+                            let mut items: Vec<Token> = Vec::new();
+                            for position in positions {
+                                items.push(self.parse_token(&value.body[position])?);
+                            }
+                            return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), items));
+                        }
+
+                        if value.kind == TokenKind::STRING {
+                            if token.body.len() == 2 {
+                                let characters: Vec<char> = value.context.chars().collect();
+                                let index = self.resolve_index(&index, characters.len(), false, &value.kind)?;
+                                return Ok(Token::new(TokenKind::STRING, characters[index].to_string(), Vec::new()));
+                            }
+
+                            let item = self.parse_token(&token.body[2])?;
+                            return self.replace_character(&value, &index, &item);
+                        }
+
+                        if value.kind == TokenKind::RANGE && token.body.len() == 2 {
+                            let length = self.indexed_range_length(&value)?;
+                            let index = self.resolve_index(&index, length, false, &value.kind)?;
+                            return Ok(Interpreter::range_item(&value, index));
+                        }
+
+                        let mut array: Token = self.materialize(value)?;
+                        let index = self.resolve_index(&index, array.body.len(), false, &array.kind)?;
+                        
+                        if token.body.len() == 2 {
+                            return self.parse_token(&array.body[index]);
+                        } else {
+                            Rc::make_mut(&mut array.body)[index] = self.parse_token(&token.body[2])?;
+                            return Ok(Token::shared(TokenKind::ARRAY, "Array".to_string(), array.body)); 
+                        }
+                    },
+                    "$_" => {
+                        // [$_ name default], missing variable without default is error
+                        self.arity(token, 1, 2)?;
+                        let variable = self.string(token, 0)?;
+                        return match env::var(&variable) {
+                            Ok(value) => Ok(Token::new(TokenKind::STRING, value, Vec::new())),
+                            Err(_) if token.body.len() == 2 => self.parse_token(&token.body[1]),
+                            Err(env::VarError::NotPresent) => Err(self.raise(&format!("Undefined environment variable {}", variable))),
+                            Err(error) => Err(self.raise(&format!("Unable to read environment variable {}: {}", variable, error))),
+                        };
+                    },
+                    "env-set" => {
+                        self.arity(token, 2, 2)?;
+                        let variable = self.string(token, 0)?;
+                        let value = self.parse_token(&token.body[1])?;
+                        let value = self.display(&value)?;
+                        if variable.is_empty() || variable.contains(['=', '\0']) || value.contains('\0') {
+                            return Err(self.raise(&format!("Invalid environment variable {}", variable)));
+                        }
+                        env::set_var(variable, value);
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "env-unset" => {
+                        self.arity(token, 1, 1)?;
+                        let variable = self.string(token, 0)?;
+                        if variable.is_empty() || variable.contains(['=', '\0']) {
+                            return Err(self.raise(&format!("Invalid environment variable {}", variable)));
+                        }
+                        env::remove_var(variable);
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "env-list" => {
+                        // map of all variables sorted by name
+                        self.arity(token, 0, 0)?;
+                        let mut variables: Vec<(String, String)> = env::vars_os()
+                            .map(|(name, value)| (name.to_string_lossy().into_owned(), value.to_string_lossy().into_owned()))
+                            .collect();
+                        variables.sort();
+                        let mut result = Token::new(TokenKind::MAP, "Map".to_string(), Vec::new());
+                        for (name, value) in variables {
+                            Rc::make_mut(&mut result.body).push(Token::new(TokenKind::ARRAY, "Array".to_string(), vec![
+                                Token::new(TokenKind::STRING, name, Vec::new()),
+                                Token::new(TokenKind::STRING, value, Vec::new()),
+                            ]));
+                        }
+                        return Ok(result);
+                    },
+                    "args-parse" => {
+                        // [args-parse spec arguments], arguments are ARGV by default
+                        self.arity(token, 1, 2)?;
+                        let spec = self.map(token, 0)?;
+                        let arguments = if token.body.len() == 2 {
+                            self.items(token, 1)?
+                        } else {
+                            match self.get_variable("ARGV") {
+                                Some(arguments) if arguments.kind == TokenKind::ARRAY => arguments.body.to_vec(),
+                                Some(_) => return Err(self.raise("Variable ARGV must be array")),
+                                None => return Err(self.raise("Variable ARGV is not defined, arguments have to be given to args-parse")),
+                            }
+                        };
+                        let mut words: Vec<String> = Vec::new();
+                        for argument in arguments.iter() {
+                            words.push(self.display(argument)?);
+                        }
+                        return self.parse_arguments(&spec, &words);
+                    },
+                    "exit" => {
+                        // [exit code], code is 0 by default
+                        self.arity(token, 0, 1)?;
+                        let code = if token.body.is_empty() { 0 } else { self.integer(token, 0)? };
+                        // operating systems keep only the lowest byte of exit code
+                        if !(0..=255).contains(&code) {
+                            return Err(self.raise(&format!("Exit code {} is out of range 0 to 255", code)));
+                        }
+                        return Err(Signal::Exit(code as i32));
+                    },
+                    "<>" => { 
+                        if token.body.len() < 3 {
+                            return Err(self.raise("Function <> takes at least 3 arguments!"));
+                        }
+                        
+                        let iterator = self.parse_token(&token.body[1])?;
+                        let variable = token.body[0].context.clone();
+                        let statements = token.body[2..].to_vec();
+
+                        let loop_variable = variable.clone();
+                        let result = self.iterate(&iterator, Box::new(move |interpreter, item| {
+                            interpreter.variables.last_mut().unwrap().insert(loop_variable.clone(), item);
+                            match interpreter.parse_block(&statements) {
+                                Ok(_) | Err(Signal::Continue) => Ok(()),
+                                Err(signal) => Err(signal),
+                            }
+                        }));
+                        self.variables.last_mut().unwrap().remove(&variable);
+                        match result {
+                            Ok(()) | Err(Signal::Break) => return Ok(iterator),
+                            Err(signal) => return Err(signal),
+                        }
+                    },
+                    "!" => {
+                        if token.body.len() != 1 {
+                            return Err(self.raise("Function ! takes exactly 1 argument"));
+                        }
+
+                        let mut value = self.parse_token(&token.body[0])?;
+                        if value.kind != TokenKind::BOOL {
+                            return Err(self.raise("Function ! takes only boolean arguments"));
+                        }
+
+                        value.context = (if value.context == "true" { "false" } else { "true" }).to_string();
+                        return Ok(value);
+                    },
+                    ".." => {
+                        // [.. 1 3] or [.. 10 1 3]
+                        if token.body.len() != 2 && token.body.len() != 3 {
+                            return Err(self.raise("Function .. takes 2 or 3 arguments"));
+                        }
+
+                        let mut bounds: Vec<isize> = Vec::new();
+                        for argument in token.body.iter() {
+                            let bound = self.parse_token(argument)?;
+                            if bound.kind != TokenKind::INT {
+                                return Err(self.raise("Arguments for function .. must be integers"));
+                            }
+                            bounds.push(bound.context.parse::<isize>().unwrap());
+                        }
+
+                        let step = *bounds.get(2).unwrap_or(&1);
+                        if step <= 0 {
+                            return Err(self.raise("Step of range must be positive"));
+                        }
+
+                        // range is lazy, items are computed while iterating
+                        let step = if bounds[0] > bounds[1] { -step } else { step };
+                        let body: Vec<Token> = vec![bounds[0], bounds[1], step].into_iter()
+                            .map(|bound| Token::new(TokenKind::INT, format!("{}", bound), Vec::new()))
+                            .collect();
+                        return Ok(Token::new(TokenKind::RANGE, "Range".to_string(), body))
+                    },
+                    "><" => {
+                        if token.body.len() < 2 {
+                            return Err(self.raise("Function >< takes at least 2 arguments!"));
+                        }
+                        
+
+                        while self.parse_token(&token.body[0])?.context != "false" {
+                            match self.parse_block(&token.body[1..]) {
+                                Ok(_) | Err(Signal::Continue) => {},
+                                Err(Signal::Break) => break,
+                                Err(signal) => return Err(signal),
+                            }
+                        }
+                        
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "break" => {
+                        if !token.body.is_empty() {
+                            return Err(self.raise("Function break takes no arguments"));
+                        }
+
+                        return Err(Signal::Break);
+                    },
+                    "continue" => {
+                        if !token.body.is_empty() {
+                            return Err(self.raise("Function continue takes no arguments"));
+                        }
+
+                        return Err(Signal::Continue);
+                    },
+                    "return" => {
+                        if token.body.len() > 1 {
+                            return Err(self.raise("Function return takes at most 1 argument"));
+                        }
+
+                        let value = match token.body.first() {
+                            Some(value) => self.parse_token(value)?,
+                            None => Token::new(TokenKind::VOID, String::new(), Vec::new()),
+                        };
+                        return Err(Signal::Return(value));
+                    },
+                    "&" => {
+                        // [& name [@ arg1 arg2] body...] or [& [@ arg1 arg2] body...] for anonymous function
+                        let anonymous = !token.body.is_empty() && token.body[0].kind == TokenKind::FUNCTION;
+                        let start = if anonymous { 0 } else { 1 };
+                        if token.body.len() < start + 1 {
+                            return Err(self.raise("Function & takes at least 2 arguments"));
+                        }
+
+                        if !anonymous && token.body[0].kind != TokenKind::WORD {
+                            return Err(self.raise("Argument 1 in function & must be function name"));
+                        }
+
+                        let parameters = &token.body[start];
+                        if parameters.kind != TokenKind::FUNCTION || parameters.context != "@" 
+                            || parameters.body.iter().any(|parameter| parameter.kind != TokenKind::WORD) {
+                            return Err(self.raise(&format!("Argument {} in function & must be array of argument names", start + 1)));
+                        }
+
+                        let name = if anonymous { "lambda".to_string() } else { token.body[0].context.clone() };
+                        // functions which yield are generators, parameters are marked so calls know it
+                        let kind = if Interpreter::yields(&token.body[start + 1..]) { "Generator" } else { "Array" };
+                        // functions created inside other function remember its variables
+                        // context of closure is module of the function
+                        let mut closure = Token::new(TokenKind::MAP, self.module.clone(), Vec::new());
+                        if self.variables.len() > 1 {
+                            for (variable, value) in self.variables.last().unwrap() {
+                                Interpreter::map_set(&mut closure, Token::new(TokenKind::STRING, variable.clone(), Vec::new()), value.clone());
+                            }
+                        }
+                        // includes in its body are relative to the file where it was defined
+                        let file = Token::new(TokenKind::STRING, self.file.to_string_lossy().into_owned(), Vec::new());
+                        let mut body = vec![Token::shared(TokenKind::ARRAY, kind.to_string(), parameters.body.clone()), closure, file];
+                        body.extend_from_slice(&token.body[start + 1..]);
+
+                        let function = Token::new(TokenKind::LAMBDA, name.clone(), body);
+                        if !anonymous {
+                            self.functions.insert(name, function.clone());
+                        }
+                        return Ok(function);
+                    },
+                    "yield" => {
+                        if token.body.len() != 1 {
+                            return Err(self.raise("Function yield takes exactly 1 argument"));
+                        }
+
+                        let value = self.parse_token(&token.body[0])?;
+                        if self.coroutine.is_none() {
+                            return Err(self.raise("Function yield used outside of generator"));
+                        }
+
+                        // generator waits here until its consumer needs next value
+                        self.yield_step(Ok(Some(value)));
+                        let resumed = HANDOVER.with(|handover| handover.borrow().as_ref().unwrap().1.recv());
+                        match resumed {
+                            Ok(true) => return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new())),
+                            Ok(false) => return Err(Signal::Stop),
+                            // interpreter is gone, nothing it owned can be touched anymore
+                            Err(_) => loop { std::thread::park() },
+                        }
+                    },
+                    "~" | "eval" => {
+                        // [eval code environment], code is string or quoted code and result is value of its last expression
+                        // with map of variables as environment code runs isolated, it doesn't see nor change anything outside
+                        self.arity(token, 1, 2)?;
+                        let code: Token = self.parse_token(&token.body[0])?;
+                        let program = match code.kind {
+                            TokenKind::STRING => tokenize(lex(&code.context))?,
+                            TokenKind::CODE => vec![code.body[0].clone()],
+                            _ => return Err(self.raise(&format!("Argument 1 in function {} must be string or code", token.context))),
+                        };
+                        let mut expanded: Vec<Token> = Vec::new();
+                        for form in program.iter() {
+                            expanded.push(self.expand(form)?);
+                        }
+                        let program = expanded;
+                        if token.body.len() == 1 {
+                            return self.parse_block(&program);
+                        }
+
+                        let environment = self.map(token, 1)?;
+                        let mut globals: HashMap<String, Token> = HashMap::new();
+                        for pair in environment.body.iter() {
+                            globals.insert(pair.body[0].context.clone(), pair.body[1].clone());
+                        }
+                        // environment stays in modules, so functions created inside still work when they are returned
+                        self.isolated += 1;
+                        let module = format!("<eval {}>", self.isolated);
+                        self.modules.insert(module.clone(), (globals, HashMap::new()));
+                        let previous = self.switch_module(&module);
+                        let frames = self.variables.split_off(1);
+                        let result = self.parse_block(&program);
+                        self.variables.extend(frames);
+                        self.switch_module(&previous);
+                        return result;
+                    },
+                    "macro" => {
+                        // [macro name [@ params] body...] gets arguments as code and returns code which replaces it
+                        // last parameter starting with ... gets array of the rest of arguments
+                        self.arity(token, 3, usize::MAX)?;
+                        if token.body[0].kind != TokenKind::WORD {
+                            return Err(self.raise("Argument 1 in function macro must be macro name"));
+                        }
+                        let mut parameters = token.body[1].clone();
+                        if parameters.kind != TokenKind::FUNCTION || parameters.context != "@"
+                            || parameters.body.iter().any(|parameter| parameter.kind != TokenKind::WORD) {
+                            return Err(self.raise("Argument 2 in function macro must be array of argument names"));
+                        }
+                        let rest = parameters.body.last().is_some_and(|parameter| parameter.context.starts_with("..."));
+                        if rest {
+                            let last = Rc::make_mut(&mut parameters.body).last_mut().unwrap();
+                            last.context = last.context[3..].to_string();
+                        }
+
+                        let mut definition = vec![parameters];
+                        definition.extend_from_slice(&token.body[2..]);
+                        let mut function = self.parse_token(&Token::new(TokenKind::FUNCTION, "&".to_string(), definition))?;
+                        function.context = token.body[0].context.clone();
+                        self.macros.insert(function.context.clone(), (function, rest));
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "quasiquote" => {
+                        // [quasiquote [-> [unquote x]]] is code with value of x inserted, unquote-splice inserts items of array
+                        self.arity(token, 1, 1)?;
+                        let mut names: Vec<String> = Vec::new();
+                        Interpreter::binders(&token.body[0], &mut names);
+                        let mut renames: HashMap<String, String> = HashMap::new();
+                        for name in names {
+                            let renamed = self.gensym(&name);
+                            renames.insert(name, renamed);
+                        }
+                        let code = self.quasiquote(&token.body[0], &renames)?;
+                        return Ok(Interpreter::quote(code));
+                    },
+                    "gensym" => {
+                        // [gensym 'name'] is word which can't clash with any other name
+                        self.arity(token, 0, 1)?;
+                        let name = if token.body.is_empty() { "g".to_string() } else { self.string(token, 0)? };
+                        let name = self.gensym(&name);
+                        return Ok(Interpreter::quote(Token::new(TokenKind::WORD, name, Vec::new())));
+                    },
+                    "expand" => {
+                        self.arity(token, 1, 1)?;
+                        let code = self.parse_token(&token.body[0])?;
+                        if code.kind != TokenKind::CODE {
+                            return Err(self.raise("Argument 1 in function expand must be code"));
+                        }
+                        let expanded = self.expand(&code.body[0])?;
+                        return Ok(Interpreter::quote(expanded));
+                    },
+                    "quote" => {
+                        // [quote [+ 1 2]] returns the code without evaluating it
+                        self.arity(token, 1, 1)?;
+                        return Ok(Interpreter::quote(token.body[0].clone()));
+                    },
+                    "code-kind" => {
+                        // call, word, string, int or float
+                        self.arity(token, 1, 1)?;
+                        let code = self.parse_token(&token.body[0])?;
+                        if code.kind != TokenKind::CODE {
+                            return Err(self.raise("Argument 1 in function code-kind must be code"));
+                        }
+                        let kind = match code.body[0].kind {
+                            TokenKind::FUNCTION => "call",
+                            ref kind => Interpreter::type_name(kind),
+                        };
+                        return Ok(Token::new(TokenKind::STRING, kind.to_string(), Vec::new()));
+                    },
+                    "code-head" | "code-args" => {
+                        // name of called function and array of its arguments as code
+                        self.arity(token, 1, 1)?;
+                        let code = self.parse_token(&token.body[0])?;
+                        if code.kind != TokenKind::CODE || code.body[0].kind != TokenKind::FUNCTION {
+                            return Err(self.raise(&format!("Argument 1 in function {} must be code of call", token.context)));
+                        }
+                        if token.context == "code-head" {
+                            return Ok(Token::new(TokenKind::STRING, code.body[0].context.clone(), Vec::new()));
+                        }
+                        let arguments: Vec<Token> = code.body[0].body.iter().map(|argument| Interpreter::quote(argument.clone())).collect();
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), arguments));
+                    },
+                    "code-word" => {
+                        self.arity(token, 1, 1)?;
+                        let name = self.string(token, 0)?;
+                        if name.is_empty() || name.contains([' ', '[', ']', '\'', '#']) || is_numeric(&name) || is_float(&name) {
+                            return Err(self.raise(&format!("Invalid name '{}'", name)));
+                        }
+                        return Ok(Interpreter::quote(Token::new(TokenKind::WORD, name, Vec::new())));
+                    },
+                    "code-build" => {
+                        // [code-build '+' [@ 1 [quote [$ x]]]], values which are not code become literals
+                        self.arity(token, 2, 2)?;
+                        let head = self.string(token, 0)?;
+                        if head.is_empty() || head.contains([' ', '[', ']', '\'', '#']) {
+                            return Err(self.raise(&format!("Invalid function name '{}'", head)));
+                        }
+                        let arguments: Vec<Token> = self.items(token, 1)?.iter().map(Interpreter::unquote).collect();
+                        return Ok(Interpreter::quote(Token::new(TokenKind::FUNCTION, head, arguments)));
+                    },
+                    "o-o" => {
+                        self.parse_block(&token.body)?;
+
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "import" => {
+                        // [import 'lib/math.wal'] makes exports available as math.name, second argument changes the namespace
+                        self.arity(token, 1, 2)?;
+                        let path = self.string(token, 0)?;
+                        let namespace = if token.body.len() == 2 {
+                            match self.parse_token(&token.body[1])? {
+                                namespace if namespace.kind == TokenKind::WORD || namespace.kind == TokenKind::STRING => namespace.context,
+                                _ => return Err(self.raise("Argument 2 in function import must be namespace name")),
+                            }
+                        } else {
+                            Path::new(&path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+                        };
+                        self.import(&path, &namespace)?;
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "assert" => {
+                        // [assert condition message]
+                        self.arity(token, 1, 2)?;
+                        let condition = self.parse_token(&token.body[0])?;
+                        if condition.kind != TokenKind::BOOL {
+                            return Err(self.raise("Argument 1 in function assert must be bool"));
+                        }
+                        if condition.context == "false" {
+                            let message = if token.body.len() == 2 { self.string(token, 1)? } else { "condition is false".to_string() };
+                            return Err(self.raise(&format!("Assertion failed: {}", message)));
+                        }
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "assert-eq" => {
+                        // [assert-eq actual expected]
+                        self.arity(token, 2, 2)?;
+                        let actual = self.parse_token(&token.body[0])?;
+                        let expected = self.parse_token(&token.body[1])?;
+                        if !Interpreter::equals(&actual, &expected) {
+                            let (actual, expected) = (self.debug(&actual)?, self.debug(&expected)?);
+                            return Err(self.raise(&format!("Assertion failed: expected {}, got {}", expected, actual)));
+                        }
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "export" => {
+                        // [export name...] inside module, names are functions or global variables
+                        self.arity(token, 1, usize::MAX)?;
+                        if self.module.is_empty() {
+                            return Err(self.raise("Function export can be used only in module"));
+                        }
+                        let mut names: Vec<String> = Vec::new();
+                        for name in token.body.iter() {
+                            if name.kind != TokenKind::WORD {
+                                return Err(self.raise("Arguments of function export must be names"));
+                            }
+                            names.push(name.context.clone());
+                        }
+                        let module = self.module.clone();
+                        self.exports.entry(module).or_default().extend(names);
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "~>" => {
+                        if token.body.len() != 1 {
+                            return Err(self.raise("Function ~> takes exactly 1 argument"));
+                        }
+
+                        let file: Token = self.parse_token(&token.body[0])?;
+                        if file.kind != TokenKind::STRING {
+                            return Err(self.raise("Argument for function ~> must be string!"));
+                        }
+
+                        let file = self.resolve(&file.context)?;
+                        self.evaluate_file(file)?;
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new())); 
+                    },
+                    "file-read" | "file-lines" => {
+                        self.arity(token, 1, 1)?;
+                        let path = self.string(token, 0)?;
+                        let content = match fs::read_to_string(&path) {
+                            Ok(content) => content,
+                            Err(error) => return Err(self.file_error("read file", &path, error)),
+                        };
+                        if token.context == "file-read" {
+                            return Ok(Token::new(TokenKind::STRING, content, Vec::new()));
+                        }
+                        let lines: Vec<Token> = content.lines().map(|line| Token::new(TokenKind::STRING, line.to_string(), Vec::new())).collect();
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), lines));
+                    },
+                    "file-write" | "file-append" => {
+                        // [file-write path content], file is created when it doesn't exist
+                        self.arity(token, 2, 2)?;
+                        let path = self.string(token, 0)?;
+                        let content = self.parse_token(&token.body[1])?;
+                        let content = self.display(&content)?;
+                        let result = fs::OpenOptions::new()
+                            .create(true)
+                            .write(true)
+                            .append(token.context == "file-append")
+                            .truncate(token.context == "file-write")
+                            .open(&path)
+                            .and_then(|mut file| file.write_all(content.as_bytes()));
+                        if let Err(error) = result {
+                            return Err(self.file_error("write file", &path, error));
+                        }
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "file-exists" => {
+                        self.arity(token, 1, 1)?;
+                        let path = self.string(token, 0)?;
+                        return Ok(Token::new(TokenKind::BOOL, format!("{}", Path::new(&path).exists()), Vec::new()));
+                    },
+                    "file-type" => {
+                        // file, directory or symlink, void when nothing is on the path
+                        self.arity(token, 1, 1)?;
+                        let path = self.string(token, 0)?;
+                        let kind = match fs::symlink_metadata(&path) {
+                            Ok(metadata) if metadata.file_type().is_symlink() => "symlink",
+                            Ok(metadata) if metadata.is_dir() => "directory",
+                            Ok(_) => "file",
+                            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new())),
+                            Err(error) => return Err(self.file_error("read metadata of", &path, error)),
+                        };
+                        return Ok(Token::new(TokenKind::STRING, kind.to_string(), Vec::new()));
+                    },
+                    "dir-list" => {
+                        // names of entries sorted alphabetically
+                        self.arity(token, 1, 1)?;
+                        let path = self.string(token, 0)?;
+                        let entries = match fs::read_dir(&path) {
+                            Ok(entries) => entries,
+                            Err(error) => return Err(self.file_error("list directory", &path, error)),
+                        };
+                        let mut names: Vec<String> = Vec::new();
+                        for entry in entries {
+                            match entry {
+                                Ok(entry) => names.push(entry.file_name().to_string_lossy().into_owned()),
+                                Err(error) => return Err(self.file_error("list directory", &path, error)),
+                            }
+                        }
+                        names.sort();
+                        let names: Vec<Token> = names.into_iter().map(|name| Token::new(TokenKind::STRING, name, Vec::new())).collect();
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), names));
+                    },
+                    "file-delete" => {
+                        // directories are deleted only when they are empty
+                        self.arity(token, 1, 1)?;
+                        let path = self.string(token, 0)?;
+                        let result = if Path::new(&path).is_dir() { fs::remove_dir(&path) } else { fs::remove_file(&path) };
+                        if let Err(error) = result {
+                            return Err(self.file_error("delete", &path, error));
+                        }
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "dir-create" => {
+                        // missing parent directories are created too
+                        self.arity(token, 1, 1)?;
+                        let path = self.string(token, 0)?;
+                        if let Err(error) = fs::create_dir_all(&path) {
+                            return Err(self.file_error("create directory", &path, error));
+                        }
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "path-join" => {
+                        // absolute part replaces everything before it
+                        self.arity(token, 1, usize::MAX)?;
+                        let mut path = PathBuf::new();
+                        for index in 0..token.body.len() {
+                            path.push(self.string(token, index)?);
+                        }
+                        return Ok(Token::new(TokenKind::STRING, path.to_string_lossy().into_owned(), Vec::new()));
+                    },
+                    "path-normalize" => {
+                        self.arity(token, 1, 1)?;
+                        let path = self.string(token, 0)?;
+                        return Ok(Token::new(TokenKind::STRING, Interpreter::normalize(&path), Vec::new()));
+                    },
+                    "path-dirname" | "path-basename" | "path-extension" => {
+                        // missing parts are empty strings, dirname of file without directory is .
+                        self.arity(token, 1, 1)?;
+                        let text = self.string(token, 0)?;
+                        let path = Path::new(&text);
+                        let result = match token.context.as_str() {
+                            "path-dirname" => match path.parent() {
+                                Some(parent) if parent.as_os_str().is_empty() => ".".to_string(),
+                                Some(parent) => parent.to_string_lossy().into_owned(),
+                                None => text.clone(),
+                            },
+                            "path-basename" => path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default(),
+                            _ => path.extension().map(|extension| extension.to_string_lossy().into_owned()).unwrap_or_default(),
+                        };
+                        return Ok(Token::new(TokenKind::STRING, result, Vec::new()));
+                    },
+                    "glob" => {
+                        // [glob 'src/**/*.wal'] returns sorted array of existing paths
+                        self.arity(token, 1, 1)?;
+                        let pattern = self.string(token, 0)?;
+                        let root = if pattern.starts_with('/') { "/" } else { "" };
+                        let components: Vec<&str> = pattern.split('/').filter(|component| !component.is_empty() && *component != ".").collect();
+                        let mut results: Vec<String> = Vec::new();
+                        Interpreter::glob(Path::new(root), &components, &mut results);
+                        results.sort();
+                        results.dedup();
+                        let results: Vec<Token> = results.into_iter().map(|path| Token::new(TokenKind::STRING, path, Vec::new())).collect();
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), results));
+                    },
+                    "run" => {
+                        // [run [@ 'ls' '-l'] [@@ 'cwd' 'src']] returns map of code, stdout and stderr
+                        self.arity(token, 1, 2)?;
+                        let arguments = self.parse_token(&token.body[0])?;
+                        let options = if token.body.len() == 2 { Some(self.map(token, 1)?) } else { None };
+                        let (mut command, input) = self.command(&arguments, options.as_ref())?;
+                        let program = command.get_program().to_string_lossy().into_owned();
+                        let child = self.spawn(command.stderr(Stdio::piped()), input, Stdio::piped())?;
+                        let output = match child.wait_with_output() {
+                            Ok(output) => output,
+                            Err(error) => return Err(self.raise(&format!("Unable to run '{}': {}", program, error))),
+                        };
+
+                        // code is void when process was killed by signal
+                        let code = match output.status.code() {
+                            Some(code) => Token::new(TokenKind::INT, format!("{}", code), Vec::new()),
+                            None => Token::new(TokenKind::VOID, String::new(), Vec::new()),
+                        };
+                        let mut record = Token::new(TokenKind::MAP, "Map".to_string(), Vec::new());
+                        for (key, value) in [
+                            ("code", code),
+                            ("stdout", Token::new(TokenKind::STRING, String::from_utf8_lossy(&output.stdout).into_owned(), Vec::new())),
+                            ("stderr", Token::new(TokenKind::STRING, String::from_utf8_lossy(&output.stderr).into_owned(), Vec::new())),
+                        ] {
+                            Interpreter::map_set(&mut record, Token::new(TokenKind::STRING, key.to_string(), Vec::new()), value);
+                        }
+                        return Ok(record);
+                    },
+                    "run-lines" => {
+                        // command runs when the generator is iterated, failure of command is error
+                        self.arity(token, 1, 2)?;
+                        let mut body = vec![self.parse_token(&token.body[0])?];
+                        if token.body.len() == 2 {
+                            body.push(self.map(token, 1)?);
+                        }
+                        return Ok(Token::new(TokenKind::GENERATOR, "run-lines".to_string(), body));
+                    },
+                    "len" => {
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        let length = match value.kind {
+                            TokenKind::ARRAY|TokenKind::MAP => value.body.len(),
+                            TokenKind::STRING => value.context.chars().count(),
+                            // length must fit in integer, not only in usize
+                            TokenKind::RANGE => match Interpreter::range_length(&value).filter(|length| isize::try_from(*length).is_ok()) {
+                                Some(length) => length,
+                                None => return Err(self.raise("Range is too long to get its length")),
+                            },
+                            _ => return Err(self.raise("Argument 1 in function len must be array, map, string or range")),
+                        };
+                        return Ok(Token::new(TokenKind::INT, format!("{}", length), Vec::new()));
+                    },
+                    "slice" => {
+                        // [slice array from to?], negative indexes count from the end
+                        self.arity(token, 2, 3)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        let start = self.integer(token, 1)?;
+                        let end = if token.body.len() == 3 { self.integer(token, 2)? } else { isize::MAX };
+                        if value.kind == TokenKind::STRING {
+                            let characters: Vec<char> = value.context.chars().collect();
+                            let (start, end) = Interpreter::slice_bounds(start, end, characters.len());
+                            return Ok(Token::new(TokenKind::STRING, characters[start..end].iter().collect(), Vec::new()));
+                        }
+                        if value.kind == TokenKind::RANGE {
+                            let length = self.indexed_range_length(&value)?;
+                            let (start, end) = Interpreter::slice_bounds(start, end, length);
+                            let items = (start..end).map(|index| Interpreter::range_item(&value, index)).collect();
+                            return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), items));
+                        }
+
+                        let items = self.items(token, 0)?;
+                        let (start, end) = Interpreter::slice_bounds(start, end, items.len());
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), items[start..end].to_vec()));
+                    },
+                    "reverse" => {
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        match value.kind {
+                            TokenKind::STRING => return Ok(Token::new(TokenKind::STRING, value.context.chars().rev().collect(), Vec::new())),
+                            TokenKind::RANGE => {
+                                let (from, _, step) = Interpreter::range_bounds(&value);
+                                let last = Interpreter::range_last(&value);
+                                let body: Vec<Token> = vec![last, from, -step].into_iter()
+                                    .map(|bound| Token::new(TokenKind::INT, format!("{}", bound), Vec::new()))
+                                    .collect();
+                                return Ok(Token::new(TokenKind::RANGE, "Range".to_string(), body));
+                            },
+                            _ => {
+                                let mut items = self.items(token, 0)?;
+                                items.reverse();
+                                return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), items));
+                            },
+                        }
+                    },
+                    "contains" | "index-of" => {
+                        self.arity(token, 2, 2)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        let needle = self.parse_token(&token.body[1])?;
+                        let index: Option<usize> = if value.kind == TokenKind::STRING {
+                            if needle.kind != TokenKind::STRING {
+                                return Err(self.raise(&format!("Argument 2 in function {} must be string when searching in string", token.context)));
+                            }
+                            value.context.find(&needle.context).map(|index| value.context[..index].chars().count())
+                        } else if value.kind == TokenKind::RANGE {
+                            Interpreter::range_position(&value, &needle)
+                        } else {
+                            self.items(token, 0)?.iter().position(|item| Interpreter::equals(item, &needle))
+                        };
+
+                        if token.context == "contains" {
+                            return Ok(Token::new(TokenKind::BOOL, format!("{}", index.is_some()), Vec::new()));
+                        }
+                        let index = index.map(|index| index as isize).unwrap_or(-1);
+                        return Ok(Token::new(TokenKind::INT, format!("{}", index), Vec::new()));
+                    },
+                    "concat" => {
+                        // joins strings when all arguments are strings, otherwise items of arrays
+                        self.arity(token, 1, usize::MAX)?;
+                        let mut values: Vec<Token> = Vec::new();
+                        for argument in token.body.iter() {
+                            values.push(self.parse_token(argument)?);
+                        }
+
+                        if values[0].kind == TokenKind::STRING {
+                            if let Some(index) = values.iter().position(|value| value.kind != TokenKind::STRING) {
+                                return Err(self.raise(&format!("Argument {} in function concat must be string", index + 1)));
+                            }
+                            let result: String = values.iter().map(|value| value.context.as_str()).collect();
+                            return Ok(Token::new(TokenKind::STRING, result, Vec::new()));
+                        }
+
+                        let mut result: Vec<Token> = Vec::new();
+                        for (index, value) in values.iter().enumerate() {
+                            match value.kind {
+                                TokenKind::ARRAY|TokenKind::RANGE|TokenKind::GENERATOR|TokenKind::MAP => result.extend(self.collect(value)?),
+                                _ => return Err(self.raise(&format!("Argument {} in function concat must be array", index + 1))),
+                            }
+                        }
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), result));
+                    },
+                    "flatten" => {
+                        // [flatten array depth?], depth is 1 by default
+                        self.arity(token, 1, 2)?;
+                        let mut result = self.items(token, 0)?;
+                        let depth = if token.body.len() == 2 { self.integer(token, 1)? } else { 1 };
+                        for _ in 0..depth {
+                            if !result.iter().any(|item| item.kind == TokenKind::ARRAY || item.kind == TokenKind::RANGE) {
+                                break;
+                            }
+
+                            let mut flat: Vec<Token> = Vec::new();
+                            for item in result {
+                                if item.kind == TokenKind::ARRAY || item.kind == TokenKind::RANGE {
+                                    flat.extend(self.collect(&item)?);
+                                } else {
+                                    flat.push(item);
+                                }
+                            }
+                            result = flat;
+                        }
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), result));
+                    },
+                    "zip" => {
+                        self.arity(token, 2, usize::MAX)?;
+                        let mut arrays: Vec<Vec<Token>> = Vec::new();
+                        for index in 0..token.body.len() {
+                            arrays.push(self.items(token, index)?);
+                        }
+
+                        let length = arrays.iter().map(|array| array.len()).min().unwrap();
+                        let result: Vec<Token> = (0..length)
+                            .map(|index| Token::new(TokenKind::ARRAY, "Array".to_string(), arrays.iter().map(|array| array[index].clone()).collect()))
+                            .collect();
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), result));
+                    },
+                    "take" | "drop" => {
+                        self.arity(token, 2, 2)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        let count = self.integer(token, 1)?;
+                        if count < 0 {
+                            return Err(self.raise(&format!("Argument 2 in function {} must not be negative", token.context)));
+                        }
+
+                        let count = count as usize;
+                        if value.kind == TokenKind::STRING {
+                            let characters = value.context.chars();
+                            let result: String = if token.context == "take" { characters.take(count).collect() } else { characters.skip(count).collect() };
+                            return Ok(Token::new(TokenKind::STRING, result, Vec::new()));
+                        }
+
+                        if token.context == "drop" {
+                            let items = self.items(token, 0)?;
+                            return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), items.into_iter().skip(count).collect()));
+                        }
+
+                        if value.kind != TokenKind::ARRAY && value.kind != TokenKind::RANGE && value.kind != TokenKind::GENERATOR {
+                            return Err(self.raise("Argument 1 in function take must be array"));
+                        }
+
+                        // take stops iterating early, so it works even with infinite generators
+                        let items: Rc<RefCell<Vec<Token>>> = Rc::new(RefCell::new(Vec::new()));
+                        let target = items.clone();
+                        if count > 0 {
+                            let result = self.iterate(&value, Box::new(move |_, item| {
+                                target.borrow_mut().push(item);
+                                if target.borrow().len() >= count { Err(Signal::Break) } else { Ok(()) }
+                            }));
+                            match result {
+                                Ok(()) | Err(Signal::Break) => {},
+                                Err(signal) => return Err(signal),
+                            }
+                        }
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), items.take()));
+                    },
+                    "first" | "last" => {
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        let item = if value.kind == TokenKind::STRING {
+                            let mut characters = value.context.chars();
+                            let character = if token.context == "first" { characters.next() } else { characters.last() };
+                            character.map(|character| Token::new(TokenKind::STRING, character.to_string(), Vec::new()))
+                        } else if value.kind == TokenKind::RANGE {
+                            let (from, _, _) = Interpreter::range_bounds(&value);
+                            let item = if token.context == "first" { from } else { Interpreter::range_last(&value) };
+                            Some(Token::new(TokenKind::INT, format!("{}", item), Vec::new()))
+                        } else if token.context == "first" && value.kind == TokenKind::GENERATOR {
+                            let item: Rc<RefCell<Option<Token>>> = Rc::new(RefCell::new(None));
+                            let target = item.clone();
+                            match self.iterate(&value, Box::new(move |_, found| {
+                                target.replace(Some(found));
+                                Err(Signal::Break)
+                            })) {
+                                Ok(()) | Err(Signal::Break) => {},
+                                Err(signal) => return Err(signal),
+                            }
+                            item.take()
+                        } else {
+                            let mut items = self.items(token, 0)?;
+                            if token.context == "first" { items.into_iter().next() } else { items.pop() }
+                        };
+
+                        match item {
+                            Some(item) => return Ok(item),
+                            None => return Err(self.raise(&format!("Function {} can't be used with empty value", token.context))),
+                        }
+                    },
+                    "next" => {
+                        // [next generator] continues generator until its next yield, void when it has ended
+                        self.arity(token, 1, 1)?;
+                        let generator = self.parse_token(&token.body[0])?;
+                        if generator.kind != TokenKind::GENERATOR {
+                            return Err(self.raise("Argument 1 in function next must be generator"));
+                        }
+
+                        let item = if generator.context == "read-lines" && generator.coroutine.is_none() {
+                            self.read_line()?.map(|line| Token::new(TokenKind::STRING, line, Vec::new()))
+                        } else if generator.coroutine.is_none() {
+                            return Err(self.raise("Function next can't be used with run-lines, its output can be only iterated"));
+                        } else {
+                            self.resume(&generator)?
+                        };
+                        return Ok(item.unwrap_or_else(|| Token::new(TokenKind::VOID, String::new(), Vec::new())));
+                    },
+                    "type-of" => {
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        return Ok(Token::new(TokenKind::STRING, Interpreter::type_name(&value.kind).to_string(), Vec::new()));
+                    },
+                    "is-int" | "is-float" | "is-number" | "is-string" | "is-bool" | "is-void" | "is-array" | "is-map" | "is-range" | "is-generator" | "is-function" | "is-code" => {
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        let result = match &token.context[3..] {
+                            "number" => value.kind == TokenKind::INT || value.kind == TokenKind::FLOAT,
+                            name => Interpreter::type_name(&value.kind) == name,
+                        };
+                        return Ok(Token::new(TokenKind::BOOL, format!("{}", result), Vec::new()));
+                    },
+                    "to-int" => {
+                        // floats are truncated towards zero, strings have to contain whole number
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        let result = match value.kind {
+                            TokenKind::INT => Some(value.context.parse::<isize>().unwrap()),
+                            TokenKind::FLOAT => {
+                                let number = value.context.parse::<f64>().unwrap().trunc();
+                                if number.is_finite() && number >= isize::MIN as f64 && number < isize::MAX as f64 { Some(number as isize) } else { None }
+                            },
+                            TokenKind::STRING => value.context.trim().parse::<isize>().ok(),
+                            TokenKind::BOOL => Some(if value.context == "true" { 1 } else { 0 }),
+                            _ => return Err(self.raise(&format!("Value of kind {} can't be converted to int", Interpreter::type_name(&value.kind)))),
+                        };
+                        return match result {
+                            Some(result) => Ok(Token::new(TokenKind::INT, format!("{}", result), Vec::new())),
+                            None => Err(self.raise(&format!("Can't convert '{}' to int", value.context))),
+                        };
+                    },
+                    "to-float" => {
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        let result = match value.kind {
+                            TokenKind::INT | TokenKind::FLOAT => value.context.parse::<f64>().ok(),
+                            TokenKind::STRING => value.context.trim().parse::<f64>().ok(),
+                            TokenKind::BOOL => Some(if value.context == "true" { 1.0 } else { 0.0 }),
+                            _ => return Err(self.raise(&format!("Value of kind {} can't be converted to float", Interpreter::type_name(&value.kind)))),
+                        };
+                        return match result {
+                            Some(result) => Ok(Interpreter::float(result)),
+                            None => Err(self.raise(&format!("Can't convert '{}' to float", value.context))),
+                        };
+                    },
+                    "to-string" => {
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        return Ok(Token::new(TokenKind::STRING, self.display(&value)?, Vec::new()));
+                    },
+                    "format" => {
+                        // [format 'Hello {}, you have {count:>3} points' name [@@ 'count' 5]]
+                        self.arity(token, 1, usize::MAX)?;
+                        let template = self.string(token, 0)?;
+                        let mut arguments: Vec<Token> = Vec::new();
+                        for argument in token.body[1..].iter() {
+                            arguments.push(self.parse_token(argument)?);
+                        }
+                        return Ok(Token::new(TokenKind::STRING, self.format(&template, &arguments)?, Vec::new()));
+                    },
+                    "to-bool" => {
+                        // numbers are true when not zero, strings have to be true or false
+                        self.arity(token, 1, 1)?;
+                        let value = self.parse_token(&token.body[0])?;
+                        let result = match value.kind {
+                            TokenKind::BOOL => Some(value.context == "true"),
+                            TokenKind::INT | TokenKind::FLOAT => Some(value.context.parse::<f64>().unwrap() != 0.0),
+                            TokenKind::STRING => value.context.trim().parse::<bool>().ok(),
+                            _ => return Err(self.raise(&format!("Value of kind {} can't be converted to bool", Interpreter::type_name(&value.kind)))),
+                        };
+                        return match result {
+                            Some(result) => Ok(Token::new(TokenKind::BOOL, format!("{}", result), Vec::new())),
+                            None => Err(self.raise(&format!("Can't convert '{}' to bool", value.context))),
+                        };
+                    },
+                    "split" => {
+                        // [split text separator?], without separator text is split by whitespace
+                        self.arity(token, 1, 2)?;
+                        let text = self.string(token, 0)?;
+                        let parts: Vec<&str> = if token.body.len() == 2 {
+                            let separator = self.string(token, 1)?;
+                            if separator.is_empty() {
+                                return Err(self.raise("Separator of function split can't be empty"));
+                            }
+                            text.split(separator.as_str()).collect()
+                        } else {
+                            text.split_whitespace().collect()
+                        };
+                        let result: Vec<Token> = parts.into_iter().map(|part| Token::new(TokenKind::STRING, part.to_string(), Vec::new())).collect();
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), result));
+                    },
+                    "join" => {
+                        // [join array separator?]
+                        self.arity(token, 1, 2)?;
+                        let items = self.items(token, 0)?;
+                        let separator = if token.body.len() == 2 { self.string(token, 1)? } else { String::new() };
+                        let mut parts: Vec<String> = Vec::new();
+                        for item in items {
+                            match item.kind {
+                                TokenKind::STRING|TokenKind::INT|TokenKind::FLOAT|TokenKind::BOOL => parts.push(item.context),
+                                _ => return Err(self.raise(&format!("Function join can't join value of kind {:?}", item.kind))),
+                            }
+                        }
+                        return Ok(Token::new(TokenKind::STRING, parts.join(&separator), Vec::new()));
+                    },
+                    "replace" => {
+                        // [replace text from to] replaces all occurrences
+                        self.arity(token, 3, 3)?;
+                        let text = self.string(token, 0)?;
+                        let from = self.string(token, 1)?;
+                        let to = self.string(token, 2)?;
+                        if from.is_empty() {
+                            return Err(self.raise("Argument 2 in function replace can't be empty"));
+                        }
+                        return Ok(Token::new(TokenKind::STRING, text.replace(&from, &to), Vec::new()));
+                    },
+                    "trim" | "trim-start" | "trim-end" | "upper" | "lower" => {
+                        self.arity(token, 1, 1)?;
+                        let text = self.string(token, 0)?;
+                        let result = match token.context.as_str() {
+                            "trim" => text.trim().to_string(),
+                            "trim-start" => text.trim_start().to_string(),
+                            "trim-end" => text.trim_end().to_string(),
+                            "upper" => text.to_uppercase(),
+                            _ => text.to_lowercase(),
+                        };
+                        return Ok(Token::new(TokenKind::STRING, result, Vec::new()));
+                    },
+                    "starts-with" | "ends-with" => {
+                        self.arity(token, 2, 2)?;
+                        let text = self.string(token, 0)?;
+                        let affix = self.string(token, 1)?;
+                        let result = if token.context == "starts-with" { text.starts_with(&affix) } else { text.ends_with(&affix) };
+                        return Ok(Token::new(TokenKind::BOOL, format!("{}", result), Vec::new()));
+                    },
+                    "repeat" => {
+                        self.arity(token, 2, 2)?;
+                        let text = self.string(token, 0)?;
+                        let count = self.integer(token, 1)?;
+                        if count < 0 {
+                            return Err(self.raise("Argument 2 in function repeat must not be negative"));
+                        }
+                        return Ok(Token::new(TokenKind::STRING, self.repeated(&text, count as usize)?, Vec::new()));
+                    },
+                    "pad-start" | "pad-end" => {
+                        // [pad-start text width fill?], width is in characters and fill is space by default
+                        self.arity(token, 2, 3)?;
+                        let text = self.string(token, 0)?;
+                        let width = self.integer(token, 1)?;
+                        let fill = if token.body.len() == 3 { self.string(token, 2)? } else { " ".to_string() };
+                        if fill.chars().count() != 1 {
+                            return Err(self.raise(&format!("Argument 3 in function {} must be single character", token.context)));
+                        }
+
+                        let missing = (width.max(0) as usize).saturating_sub(text.chars().count());
+                        let padding = self.repeated(&fill, missing)?;
+                        let result = if token.context == "pad-start" { padding + &text } else { text + &padding };
+                        return Ok(Token::new(TokenKind::STRING, result, Vec::new()));
+                    },
+                    "map" | "filter" => {
+                        self.arity(token, 2, 2)?;
+                        let function = self.callable(token, 0)?;
+                        let mut result: Vec<Token> = Vec::new();
+                        for item in self.items(token, 1)? {
+                            let value = self.apply(function.clone(), vec![item.clone()])?;
+                            if token.context == "map" {
+                                result.push(value);
+                            } else if value.context != "false" {
+                                result.push(item);
+                            }
+                        }
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), result));
+                    },
+                    "reduce" => {
+                        // [reduce function array initial?], without initial value first item is used
+                        self.arity(token, 2, 3)?;
+                        let function = self.callable(token, 0)?;
+                        let mut items = self.items(token, 1)?.into_iter();
+                        let mut result = if token.body.len() == 3 {
+                            self.parse_token(&token.body[2])?
+                        } else {
+                            match items.next() {
+                                Some(item) => item,
+                                None => return Err(self.raise("Function reduce can't be used with empty value without initial value")),
+                            }
+                        };
+
+                        for item in items {
+                            result = self.apply(function.clone(), vec![result, item])?;
+                        }
+                        return Ok(result);
+                    },
+                    "any" | "all" => {
+                        // stops at first item which decides the result, so it works with infinite generators
+                        self.arity(token, 2, 2)?;
+                        let function = self.callable(token, 0)?;
+                        let iterable = self.parse_token(&token.body[1])?;
+                        let wanted = token.context == "any";
+                        let found = Rc::new(Cell::new(false));
+                        let target = found.clone();
+                        match self.iterate(&iterable, Box::new(move |interpreter, item| {
+                            if (interpreter.apply(function.clone(), vec![item])?.context != "false") == wanted {
+                                target.set(true);
+                                return Err(Signal::Break);
+                            }
+                            Ok(())
+                        })) {
+                            Ok(()) | Err(Signal::Break) => {},
+                            Err(signal) => return Err(signal),
+                        }
+                        return Ok(Token::new(TokenKind::BOOL, format!("{}", found.get() == wanted), Vec::new()));
+                    },
+                    "sort" | "sort-by" => {
+                        // [sort array comparator?] where comparator returns negative, zero or positive integer
+                        // [sort-by key array] compares results of key function
+                        let (function, items) = if token.context == "sort" {
+                            self.arity(token, 1, 2)?;
+                            let items = self.items(token, 0)?;
+                            let function = if token.body.len() == 2 { Some(self.callable(token, 1)?) } else { None };
+                            (function, items)
+                        } else {
+                            self.arity(token, 2, 2)?;
+                            (Some(self.callable(token, 0)?), self.items(token, 1)?)
+                        };
+
+                        let mut keyed: Vec<(Token, Token)> = Vec::new();
+                        for item in items {
+                            let key = match (&function, token.context.as_str()) {
+                                (Some(function), "sort-by") => self.apply(function.clone(), vec![item.clone()])?,
+                                _ => item.clone(),
+                            };
+                            keyed.push((key, item));
+                        }
+
+                        // sort_by can't fail, so the first error is kept and returned afterwards
+                        let mut failure: Option<Signal> = None;
+                        keyed.sort_by(|(left, _), (right, _)| {
+                            if failure.is_some() {
+                                return Ordering::Equal;
+                            }
+
+                            let ordering = match (&function, token.context.as_str()) {
+                                (Some(function), "sort") => match self.apply(function.clone(), vec![left.clone(), right.clone()]) {
+                                    Ok(result) if result.kind == TokenKind::INT => Ok(result.context.parse::<isize>().unwrap().cmp(&0)),
+                                    Ok(_) => Err(self.raise("Comparator of function sort must return integer")),
+                                    Err(signal) => Err(signal),
+                                },
+                                _ => self.compare(left, right),
+                            };
+                            ordering.unwrap_or_else(|signal| {
+                                failure = Some(signal);
+                                Ordering::Equal
+                            })
+                        });
+
+                        if let Some(signal) = failure {
+                            return Err(signal);
+                        }
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), keyed.into_iter().map(|(_, item)| item).collect()));
+                    },
+                    "group-by" => {
+                        self.arity(token, 2, 2)?;
+                        let function = self.callable(token, 0)?;
+                        let mut groups = Token::new(TokenKind::MAP, "Map".to_string(), Vec::new());
+                        for item in self.items(token, 1)? {
+                            let key = self.apply(function.clone(), vec![item.clone()])?;
+                            let mut group = Interpreter::map_get(&groups, &key).cloned()
+                                .unwrap_or_else(|| Token::new(TokenKind::ARRAY, "Array".to_string(), Vec::new()));
+                            Rc::make_mut(&mut group.body).push(item);
+                            Interpreter::map_set(&mut groups, key, group);
+                        }
+                        return Ok(groups);
+                    },
+                    "unique" => {
+                        self.arity(token, 1, 1)?;
+                        let mut result: Vec<Token> = Vec::new();
+                        for item in self.items(token, 0)? {
+                            if !result.iter().any(|found| Interpreter::equals(found, &item)) {
+                                result.push(item);
+                            }
+                        }
+                        return Ok(Token::new(TokenKind::ARRAY, "Array".to_string(), result));
+                    },
+                    "push" => {
+                        // [push variable value...]
+                        self.arity(token, 2, usize::MAX)?;
+                        let mut values: Vec<Token> = Vec::new();
+                        for value in token.body[1..].iter() {
+                            values.push(self.parse_token(value)?);
+                        }
+
+                        self.modify_variable(token, |interpreter, array| {
+                            interpreter.force(array)?;
+                            if array.kind != TokenKind::ARRAY {
+                                return Err(interpreter.raise("Argument 1 in function push must be array variable"));
+                            }
+                            Rc::make_mut(&mut array.body).extend(values);
+                            Ok(())
+                        })?;
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "pop" => {
+                        self.arity(token, 1, 1)?;
+                        let item = self.modify_variable(token, |interpreter, array| {
+                            interpreter.force(array)?;
+                            if array.kind != TokenKind::ARRAY {
+                                return Err(interpreter.raise("Argument 1 in function pop must be array variable"));
+                            }
+                            match Rc::make_mut(&mut array.body).pop() {
+                                Some(item) => Ok(item),
+                                None => Err(interpreter.raise("Function pop can't be used with empty array")),
+                            }
+                        })?;
+                        return self.parse_token(&item);
+                    },
+                    "insert" => {
+                        // [insert variable index value], for maps index is key
+                        self.arity(token, 3, 3)?;
+                        let index = self.parse_token(&token.body[1])?;
+                        let value = self.parse_token(&token.body[2])?;
+                        self.modify_variable(token, |interpreter, collection| {
+                            interpreter.force(collection)?;
+                            match collection.kind {
+                                TokenKind::MAP => Interpreter::map_set(collection, index, value),
+                                TokenKind::ARRAY => {
+                                    let index = interpreter.resolve_index(&index, collection.body.len(), true, &collection.kind)?;
+                                    Rc::make_mut(&mut collection.body).insert(index, value);
+                                },
+                                _ => return Err(interpreter.raise("Argument 1 in function insert must be array or map variable")),
+                            }
+                            Ok(())
+                        })?;
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "remove" => {
+                        // [remove variable index], for maps index is key
+                        self.arity(token, 2, 2)?;
+                        let index = self.parse_token(&token.body[1])?;
+                        let item = self.modify_variable(token, |interpreter, collection| {
+                            interpreter.force(collection)?;
+                            match collection.kind {
+                                TokenKind::MAP => match collection.body.iter().position(|pair| Interpreter::equals(&pair.body[0], &index)) {
+                                    Some(position) => Ok(Rc::make_mut(&mut collection.body).remove(position).body[1].clone()),
+                                    None => Err(interpreter.raise(&format!("Key {} not found in map", index.context))),
+                                },
+                                TokenKind::ARRAY => {
+                                    let index = interpreter.resolve_index(&index, collection.body.len(), false, &collection.kind)?;
+                                    Ok(Rc::make_mut(&mut collection.body).remove(index))
+                                },
+                                _ => Err(interpreter.raise("Argument 1 in function remove must be array or map variable")),
+                            }
+                        })?;
+                        return self.parse_token(&item);
+                    },
+                    "set-at" => {
+                        // [set-at variable index index... value] changes item of nested arrays and maps
+                        self.arity(token, 3, usize::MAX)?;
+                        let mut path: Vec<Token> = Vec::new();
+                        for index in token.body[1..token.body.len() - 1].iter() {
+                            path.push(self.parse_token(index)?);
+                        }
+                        let value = self.parse_token(&token.body[token.body.len() - 1])?;
+                        self.modify_variable(token, |interpreter, collection| interpreter.set_path(collection, &path, value))?;
+                        return Ok(Token::new(TokenKind::VOID, String::new(), Vec::new()));
+                    },
+                    "try" => {
+                        // [try expression error handler...]
+                        if token.body.len() < 3 {
+                            return Err(self.raise("Function try takes at least 3 arguments"));
+                        }
+
+                        match self.parse_token(&token.body[0]) {
+                            Err(Signal::Error(message, _, _)) => {
+                                let variable = token.body[1].context.clone();
+                                self.variables.last_mut().unwrap().insert(variable.clone(), Token::new(TokenKind::STRING, message, Vec::new()));
+                                let result = self.parse_block(&token.body[2..]);
+                                self.variables.last_mut().unwrap().remove(&variable);
+                                return result;
+                            },
+                            result => return result,
+                        }
+                    },
+                    "throw" => {
+                        if token.body.len() != 1 {
+                            return Err(self.raise("Function throw takes exactly 1 argument"));
+                        }
+
+                        let message = self.parse_token(&token.body[0])?;
+                        return Err(self.raise(&message.context));
+                    },
+                    name => {
+                        let function = match self.functions.get(name) {
+                            Some(function) => function.clone(),
+                            None => match self.get_variable(name) {
+                                Some(value) if value.kind == TokenKind::LAMBDA => value.clone(),
+                                _ => return Err(self.raise("Undefined function")), // TODO position
+                            },
+                        };
+
+                        // name of function passed as argument is resolved here, function can run in module which doesn't know it
+                        let mut arguments: Vec<Token> = Vec::new();
+                        for argument in token.body.iter() {
+                            let argument = self.parse_token(argument)?;
+                            match self.functions.get(&argument.context) {
+                                Some(function) if argument.kind == TokenKind::WORD => arguments.push(function.clone()),
+                                _ => arguments.push(argument),
+                            }
+                        }
+
+                        if tail_call && function.body[0].context != "Generator" {
+                            return Err(Signal::TailCall(function, arguments));
+                        }
+                        self.apply(function, arguments)
+                    },
+                },
+            TokenKind::INT|TokenKind::FLOAT|TokenKind::STRING|TokenKind::VOID|TokenKind::BOOL|TokenKind::WORD|TokenKind::ARRAY|TokenKind::LAMBDA|TokenKind::RANGE|TokenKind::GENERATOR|TokenKind::MAP|TokenKind::CODE => Ok(token.clone()),
+        }
+    }
+
+    fn parse(&mut self, program: Vec<Token>) -> Result<(), Signal> {
+        for token in program.iter() {
+            // every form is expanded right before it runs, so it can use macros defined above it
+            let token = self.expand(token)?;
+            self.parse_token(&token)?;
+        }
+
+        Ok(())
+    }
+
+    // prints program with expanded macros, only definitions of macros, functions and imports are evaluated, so macros can use them
+    fn print_expanded(&mut self, program: Vec<Token>) -> Result<(), Signal> {
+        for token in program.iter() {
+            let token = self.expand(token)?;
+            if token.kind == TokenKind::FUNCTION && ["macro", "&", "import"].contains(&token.context.as_str()) {
+                self.parse_token(&token)?;
+            }
+            let source = self.source(&token)?;
+            self.write(&format!("{}\n", source), false)?;
+        }
+
+        Ok(())
+    }
+}
+
+// evaluates test file, or code of test from standard library, and runs its tests
+fn test_file(interpreter: &mut Interpreter, name: &str, code: Option<&str>) -> Result<(usize, usize), Signal> {
+    let result = match code {
+        Some(code) => interpreter.evaluate_code(PathBuf::from(name), code),
+        None => {
+            interpreter.enter_main(name);
+            interpreter.evaluate_file(PathBuf::from(name))
+        },
+    }.and_then(|_| interpreter.run_tests(name));
+    let _ = interpreter.flush();
+    result
+}
+
+// runs test functions of every file, std runs tests of standard library, returns exit code
+fn test(files: Vec<String>, max_depth: usize) -> i32 {
+    let (mut passed, mut failed) = (0, 0);
+    for file in files {
+        let sources: Vec<(String, Option<&str>)> = if file == "std" {
+            STD_TESTS.iter().map(|(name, code)| (name.to_string(), Some(*code))).collect()
+        } else {
+            vec![(file, None)]
+        };
+
+        for (name, code) in sources {
+            let mut interpreter = Interpreter::new();
+            interpreter.max_depth = max_depth;
+            interpreter.limit_stack(STACK);
+            let result = test_file(&mut interpreter, &name, code);
+            match result {
+                Ok((file_passed, file_failed)) => {
+                    passed += file_passed;
+                    failed += file_failed;
+                },
+                Err(signal) => {
+                    failed += 1;
+                    let message = match signal {
+                        Signal::Error(message, line, pos) => format!("{} at line {}, col {}.", message, line, pos),
+                        Signal::Exit(code) => format!("exited with code {}", code),
+                        _ => "break, continue or return used outside of function".to_string(),
+                    };
+                    println!("FAILED {}: {}", name, message);
+                },
+            }
+        }
+    }
+
+    println!("{} passed, {} failed", passed, failed);
+    if failed > 0 { 1 } else { 0 }
+}
+
+fn main() {
+
+    let mut max_depth: usize = 10000;
+    let mut testing = false;
+    let mut expanding = false;
+    let mut args = env::args().skip(1);
+    let mut filename: Option<String> = None;
+    while let Some(arg) = args.next() {
+        if arg == "--max-depth" {
+            max_depth = match args.next().and_then(|depth| depth.parse::<usize>().ok()) {
+                Some(depth) => depth,
+                None => error("Option --max-depth takes positive integer", 0, 0),
+            };
+        } else if arg == "--test" {
+            testing = true;
+        } else if arg == "--expand" {
+            expanding = true;
+        } else {
+            filename = Some(arg);
+            break;
+        }
+    }
+
+    // everything after the script belongs to the script
+    let arguments: Vec<String> = args.collect();
+    let filename = match filename {
+        Some(filename) => filename,
+        None => error("Usage: wal [--max-depth N] [--test] [--expand] file [arguments...]", 0, 0),
+    };
+    let code = if testing {
+        String::new()
+    } else {
+        if !Path::new(&filename).exists() {
+            error(&format!("File {} not found", filename), 0, 0);
+        }
+        match fs::read_to_string(&filename) {
+            Ok(code) => code,
+            Err(reason) => error(&format!("Unable to read file '{}': {}", filename, reason), 0, 0),
+        }
+    };
+
+    // wal calls are recursive in rust, code nested deeper than the stack allows fails with error
+    let runner = std::thread::Builder::new().stack_size(STACK).spawn(move || {
+        // with --test all arguments are test files
+        if testing {
+            exit(test(std::iter::once(filename).chain(arguments).collect(), max_depth));
+        }
+
+        let mut interpreter: Interpreter = Interpreter::new();
+        interpreter.max_depth = max_depth;
+        interpreter.limit_stack(STACK);
+        interpreter.enter_main(&filename);
+        interpreter.set_arguments(arguments);
+        let result = tokenize(lex(&code)).and_then(|program| if expanding {
+            interpreter.print_expanded(program)
+        } else {
+            interpreter.parse(program)
+        });
+        exit(interpreter.finish(result));
+    });
+    let runner = match runner {
+        Ok(runner) => runner,
+        Err(reason) => error(&format!("Unable to start interpreter: {}", reason), 0, 0),
+    };
+    if runner.join().is_err() {
+        exit(-1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // stream writing to memory, clones share the content, so it can be read after the interpreter is done with it
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Buffer {
+        fn contents(&self) -> String {
+            String::from_utf8_lossy(&self.0.borrow()).into_owned()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(data);
+            Ok(data.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // runs code with input and returns what it wrote to output and error stream and its exit code
+    fn run(code: &str, input: &str) -> (String, String, i32) {
+        let (output, errors) = (Buffer::default(), Buffer::default());
+        let mut interpreter = Interpreter::with_streams(
+            Box::new(std::io::Cursor::new(input.to_string())),
+            Box::new(output.clone()),
+            Box::new(errors.clone()),
+        );
+        let result = interpreter.evaluate_code(PathBuf::from("test.wal"), code);
+        let code = interpreter.finish(result);
+        (output.contents(), errors.contents(), code)
+    }
+
+    #[test]
+    fn output_and_errors_are_captured() {
+        let (output, errors, code) = run("[-> 'hello ' [<-]] [print 1 2] [eprint 'oops'] [eprintln '!']", "wal\n");
+        assert_eq!(output, "hello wal\n12");
+        assert_eq!(errors, "oops!\n");
+        assert_eq!(code, 0);
+    }
+
+    #[test]
+    fn input_is_read_until_its_end() {
+        let (output, _, _) = run("[-> [<-]] [-> [read-bytes 2]] [<> line [read-lines] [print [upper [$ line]] ',']] [-> [is-void [<-]] [is-void [read-all]] [is-void [read-bytes 1]]]", "first\nab\nc\r\nd");
+        assert_eq!(output, "first\nab\n,C,D,truetruetrue\n");
+
+        let (output, _, _) = run("[-> [read-bytes 3] '|' [read-all]] [-> [read-all]]", "héllo");
+        assert_eq!(output, "hé|llo\n\n");
+    }
+
+    #[test]
+    fn modules_see_arguments_of_script() {
+        let output = Buffer::default();
+        let mut interpreter = Interpreter::with_streams(Box::new(std::io::empty()), Box::new(output.clone()), Box::new(Buffer::default()));
+        interpreter.set_arguments(vec!["--verbose".to_string()]);
+        let code = "[import 'modules/cli.wal'] [-> [cli.arguments]] [-> [@$ [cli.options] 'verbose']]";
+        let result = interpreter.evaluate_code(PathBuf::from("tests/main.wal"), code);
+        assert_eq!(interpreter.finish(result), 0);
+        assert_eq!(output.contents(), "[@ '--verbose']\ntrue\n");
+    }
+
+    #[test]
+    fn fatal_error_goes_to_error_stream() {
+        let (output, errors, code) = run("[-> 'before']\n[throw 'broken']", "");
+        assert_eq!(output, "before\n");
+        assert_eq!(errors, "[ERROR] broken at line 2, col 1.\n");
+        assert_eq!(code, -1);
+    }
+
+    // tests written in wal, tests/*.test.wal and tests of standard library
+    #[test]
+    fn wal_tests_pass() {
+        let runner = std::thread::Builder::new().stack_size(STACK).spawn(move || {
+            let mut files: Vec<String> = fs::read_dir("tests").unwrap().flatten()
+                .map(|entry| entry.path().to_string_lossy().into_owned())
+                .filter(|path| path.ends_with(".test.wal"))
+                .collect();
+            files.sort();
+            let mut sources: Vec<(String, Option<&str>)> = files.into_iter().map(|file| (file, None)).collect();
+            sources.extend(STD_TESTS.iter().map(|(name, code)| (name.to_string(), Some(*code))));
+
+            let mut failures: Vec<String> = Vec::new();
+            for (name, code) in sources {
+                let output = Buffer::default();
+                let mut interpreter = Interpreter::with_streams(Box::new(std::io::empty()), Box::new(output.clone()), Box::new(Buffer::default()));
+                interpreter.limit_stack(STACK);
+                match test_file(&mut interpreter, &name, code) {
+                    Ok((0, _)) => failures.push(format!("{} has no tests", name)),
+                    Ok((_, 0)) => {},
+                    Ok(_) => failures.push(output.contents()),
+                    Err(signal) => failures.push(format!("{}: {:?}", name, signal)),
+                }
+            }
+            failures
+        }).unwrap();
+
+        let failures = runner.join().unwrap();
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn exit_code_is_returned() {
+        let (output, errors, code) = run("[print 'done'] [exit 3]", "");
+        assert_eq!((output.as_str(), errors.as_str(), code), ("done", "", 3));
+    }
+
+    #[test]
+    fn recursion_stops_at_maximum_depth() {
+        let runner = std::thread::Builder::new().stack_size(STACK).spawn(|| {
+            let mut interpreter = Interpreter::with_streams(Box::new(std::io::empty()), Box::new(Buffer::default()), Box::new(Buffer::default()));
+            interpreter.max_depth = 100;
+            interpreter.limit_stack(STACK);
+            let code = "[& depth [@ n] [=< [= [$ n] 0] 0 [+ 1 [depth [- [$ n] 1]]]]] [depth 99] [depth 100]";
+            match interpreter.evaluate_code(PathBuf::from("test.wal"), code) {
+                Err(Signal::Error(message, _, _)) => message,
+                result => format!("{:?}", result),
+            }
+        }).unwrap();
+        let message = runner.join().unwrap();
+        assert!(message.starts_with("Stack overflow, maximum depth of 100 calls exceeded"), "{}", message);
+    }
 }